@@ -0,0 +1,240 @@
+use crate::bindings::theater::simple::runtime::get_chain;
+use crate::bindings::theater::simple::store;
+use crate::bindings::theater::simple::supervisor::get_child_events;
+use crate::bindings::theater::simple::timing::now;
+use crate::logging::warn;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, from_slice, to_vec};
+use std::collections::VecDeque;
+
+/// Everything the task and its children recorded, merged into one timeline
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AuditDocument {
+    pub task_id: String,
+    pub exported_at: u64,
+    pub sources: Vec<AuditSource>,
+    pub events: Vec<AuditEvent>,
+}
+
+/// A chain that was collected, or the reason it couldn't be
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AuditSource {
+    pub actor_id: String,
+    pub role: String,
+    pub events: usize,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AuditEvent {
+    pub actor_id: String,
+    pub role: String,
+    pub timestamp: u64,
+    pub event_type: String,
+    pub description: Option<String>,
+    pub hash: String,
+    pub parent_hash: Option<String>,
+}
+
+/// Collects our own chain and the chains of the given children, as
+/// `(role, actor_id)` pairs, ordered by time. Events are kept in chain order
+/// within each actor.
+pub fn export(task_id: &str, children: &[(String, String)]) -> AuditDocument {
+    let mut sources = vec![];
+    let own_events = own_events(task_id);
+    sources.push(AuditSource {
+        actor_id: task_id.to_string(),
+        role: "task_manager".to_string(),
+        events: own_events.len(),
+        error: None,
+    });
+    let mut chains = vec![own_events];
+
+    for (role, actor_id) in children {
+        match child_events(role, actor_id) {
+            Ok(child_events) => {
+                sources.push(AuditSource {
+                    actor_id: actor_id.clone(),
+                    role: role.clone(),
+                    events: child_events.len(),
+                    error: None,
+                });
+                chains.push(child_events);
+            }
+            Err(e) => {
+                warn(&format!("Failed to collect events of {}: {}", actor_id, e));
+                sources.push(AuditSource {
+                    actor_id: actor_id.clone(),
+                    role: role.clone(),
+                    events: 0,
+                    error: Some(e),
+                });
+            }
+        }
+    }
+
+    AuditDocument {
+        task_id: task_id.to_string(),
+        exported_at: now(),
+        sources,
+        events: merge_chains(chains),
+    }
+}
+
+/// Puts the document in the content store and returns its hash
+pub fn store_document(store_id: &str, document: &AuditDocument) -> Result<String, String> {
+    let data =
+        to_vec(document).map_err(|e| format!("Failed to serialize audit document: {}", e))?;
+    let content_ref = store::store(store_id, &data)
+        .map_err(|e| format!("Failed to store audit document: {}", e))?;
+    let label = format!("task-audit-{}", document.task_id);
+    if let Err(e) = store::label(store_id, &label, &content_ref) {
+        warn(&format!("Failed to label audit document: {}", e));
+    }
+    Ok(content_ref.hash)
+}
+
+/// Interleaves the chains by time, taking the earliest head each step, so
+/// every chain stays in its own order. Ties go to the earlier chain.
+fn merge_chains(chains: Vec<Vec<AuditEvent>>) -> Vec<AuditEvent> {
+    let mut chains: Vec<VecDeque<AuditEvent>> = chains.into_iter().map(VecDeque::from).collect();
+    let mut merged = vec![];
+    while let Some((_, next)) = chains
+        .iter()
+        .enumerate()
+        .filter_map(|(index, chain)| chain.front().map(|event| (event.timestamp, index)))
+        .min()
+    {
+        merged.extend(chains[next].pop_front());
+    }
+    merged
+}
+
+/// Our own chain carries no timestamps, so they are read from the event
+/// data when present. An event without one takes the previous event's, and
+/// events before the first timestamped one take that one's.
+fn own_events(task_id: &str) -> Vec<AuditEvent> {
+    let chain = get_chain().events;
+    let details: Vec<EventDetails> = chain
+        .iter()
+        .map(|meta_event| EventDetails::parse(&meta_event.event.data))
+        .collect();
+    let mut timestamp = details
+        .iter()
+        .find_map(|details| details.timestamp)
+        .unwrap_or_default();
+
+    chain
+        .into_iter()
+        .zip(details)
+        .map(|(meta_event, details)| {
+            timestamp = details.timestamp.unwrap_or(timestamp);
+            AuditEvent {
+                actor_id: task_id.to_string(),
+                role: "task_manager".to_string(),
+                timestamp,
+                event_type: meta_event.event.event_type,
+                description: details.description,
+                hash: hex(&meta_event.hash.to_be_bytes()),
+                parent_hash: meta_event.event.parent.map(|hash| hex(&hash.to_be_bytes())),
+            }
+        })
+        .collect()
+}
+
+fn child_events(role: &str, actor_id: &str) -> Result<Vec<AuditEvent>, String> {
+    let events = get_child_events(actor_id)?;
+    Ok(events
+        .into_iter()
+        .map(|event| AuditEvent {
+            actor_id: actor_id.to_string(),
+            role: role.to_string(),
+            timestamp: event.timestamp,
+            description: EventDetails::parse(&event.data).description,
+            event_type: event.event_type,
+            hash: hex(&event.hash),
+            parent_hash: event.parent_hash.as_deref().map(hex),
+        })
+        .collect())
+}
+
+/// The parts of the runtime's event data we report, when it is JSON
+#[derive(Default)]
+struct EventDetails {
+    timestamp: Option<u64>,
+    description: Option<String>,
+}
+
+impl EventDetails {
+    fn parse(data: &[u8]) -> Self {
+        match from_slice::<Value>(data) {
+            Ok(data) => Self {
+                timestamp: data.get("timestamp").and_then(Value::as_u64),
+                description: data
+                    .get("description")
+                    .and_then(Value::as_str)
+                    .map(str::to_string),
+            },
+            Err(_) => Self::default(),
+        }
+    }
+}
+
+/// Hashes are reported as lowercase hex of their bytes, whichever chain
+/// they come from
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(actor_id: &str, timestamp: u64, hash: &str) -> AuditEvent {
+        AuditEvent {
+            actor_id: actor_id.to_string(),
+            role: "test".to_string(),
+            timestamp,
+            event_type: "test".to_string(),
+            description: None,
+            hash: hash.to_string(),
+            parent_hash: None,
+        }
+    }
+
+    fn hashes(events: &[AuditEvent]) -> Vec<&str> {
+        events.iter().map(|event| event.hash.as_str()).collect()
+    }
+
+    #[test]
+    fn interleaves_chains_by_time() {
+        let merged = merge_chains(vec![
+            vec![event("task", 10, "t1"), event("task", 30, "t2")],
+            vec![event("chat", 20, "c1"), event("chat", 40, "c2")],
+        ]);
+        assert_eq!(hashes(&merged), vec!["t1", "c1", "t2", "c2"]);
+    }
+
+    #[test]
+    fn keeps_each_chain_in_order() {
+        // A timestamp going backwards doesn't pull an event ahead of its parent
+        let merged = merge_chains(vec![
+            vec![
+                event("task", 10, "t1"),
+                event("task", 50, "t2"),
+                event("task", 5, "t3"),
+            ],
+            vec![event("chat", 20, "c1")],
+        ]);
+        assert_eq!(hashes(&merged), vec!["t1", "c1", "t2", "t3"]);
+    }
+
+    #[test]
+    fn ties_go_to_the_earlier_chain() {
+        let merged = merge_chains(vec![
+            vec![event("task", 10, "t1")],
+            vec![event("chat", 10, "c1")],
+        ]);
+        assert_eq!(hashes(&merged), vec!["t1", "c1"]);
+    }
+}
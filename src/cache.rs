@@ -0,0 +1,292 @@
+use crate::bindings::theater::simple::store::{self, ContentRef};
+use crate::logging::warn;
+use crate::protocol::McpResponse;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, from_slice, to_vec};
+use std::collections::HashMap;
+
+/// Which tools may have their results reused, and where results are kept
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ToolCacheConfig {
+    /// Idempotent tools by name
+    #[serde(default)]
+    pub tools: HashMap<String, CachedTool>,
+    /// Keep cached responses in the content store instead of in state
+    pub store_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CachedTool {
+    /// How long a result stays valid; forever when unset
+    pub ttl_ms: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CacheStats {
+    pub hits: u32,
+    pub misses: u32,
+    pub entries: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct CacheEntry {
+    stored_at: u64,
+    #[serde(flatten)]
+    data: CachedData,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+enum CachedData {
+    Response(McpResponse),
+    ContentRef(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ToolCache {
+    config: ToolCacheConfig,
+    entries: HashMap<String, CacheEntry>,
+    hits: u32,
+    misses: u32,
+}
+
+impl ToolCache {
+    pub fn new(config: ToolCacheConfig) -> Self {
+        Self {
+            config,
+            entries: HashMap::new(),
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn is_cached_tool(&self, tool: &str) -> bool {
+        self.config.tools.contains_key(tool)
+    }
+
+    /// Returns the stored response for the call, if there is a fresh one.
+    /// Only counts towards the statistics for idempotent tools.
+    pub fn lookup(
+        &mut self,
+        server: &str,
+        tool: &str,
+        args: &Value,
+        now: u64,
+    ) -> Option<McpResponse> {
+        let ttl_ms = self.config.tools.get(tool)?.ttl_ms;
+        let key = cache_key(server, tool, args);
+
+        let fresh = self.entries.get(&key).filter(|entry| match ttl_ms {
+            Some(ttl_ms) => now.saturating_sub(entry.stored_at) < ttl_ms,
+            None => true,
+        });
+        let response = fresh.and_then(|entry| self.load(entry));
+
+        match response {
+            Some(response) => {
+                self.hits += 1;
+                Some(response)
+            }
+            None => {
+                self.entries.remove(&key);
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Keeps a successful response to an idempotent tool
+    pub fn insert(
+        &mut self,
+        server: &str,
+        tool: &str,
+        args: &Value,
+        response: &McpResponse,
+        now: u64,
+    ) {
+        let is_error = response.error.is_some()
+            || response
+                .result
+                .as_ref()
+                .and_then(|result| result.get("isError"))
+                .and_then(Value::as_bool)
+                == Some(true);
+        if !self.is_cached_tool(tool) || is_error {
+            return;
+        }
+
+        let data = match &self.config.store_id {
+            Some(store_id) => match save(store_id, response) {
+                Ok(hash) => CachedData::ContentRef(hash),
+                Err(e) => {
+                    warn(&format!("Failed to cache result of {}: {}", tool, e));
+                    return;
+                }
+            },
+            None => CachedData::Response(response.clone()),
+        };
+
+        self.entries.insert(
+            cache_key(server, tool, args),
+            CacheEntry {
+                stored_at: now,
+                data,
+            },
+        );
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits,
+            misses: self.misses,
+            entries: self.entries.len(),
+        }
+    }
+
+    fn load(&self, entry: &CacheEntry) -> Option<McpResponse> {
+        match (&entry.data, &self.config.store_id) {
+            (CachedData::Response(response), _) => Some(response.clone()),
+            (CachedData::ContentRef(hash), Some(store_id)) => {
+                let content_ref = ContentRef { hash: hash.clone() };
+                store::get(store_id, &content_ref)
+                    .map_err(|e| format!("Failed to load cached result {}: {}", hash, e))
+                    .and_then(|data| {
+                        from_slice(&data)
+                            .map_err(|e| format!("Failed to parse cached result {}: {}", hash, e))
+                    })
+                    .inspect_err(|e| warn(e))
+                    .ok()
+            }
+            (CachedData::ContentRef(_), None) => None,
+        }
+    }
+}
+
+fn save(store_id: &str, response: &McpResponse) -> Result<String, String> {
+    let data = to_vec(response).map_err(|e| format!("Failed to serialize response: {}", e))?;
+    let content_ref = store::store(store_id, &data)?;
+    Ok(content_ref.hash)
+}
+
+fn cache_key(server: &str, tool: &str, args: &Value) -> String {
+    format!("{}/{}/{}", server, tool, canonical_json(args))
+}
+
+/// Serializes with object keys sorted, so equal args give equal keys
+fn canonical_json(value: &Value) -> String {
+    match value {
+        Value::Object(object) => {
+            let mut keys: Vec<&String> = object.keys().collect();
+            keys.sort();
+            let fields: Vec<String> = keys
+                .into_iter()
+                .map(|key| {
+                    format!(
+                        "{}:{}",
+                        Value::String(key.clone()),
+                        canonical_json(&object[key])
+                    )
+                })
+                .collect();
+            format!("{{{}}}", fields.join(","))
+        }
+        Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(canonical_json).collect();
+            format!("[{}]", items.join(","))
+        }
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::McpError;
+    use serde_json::json;
+
+    fn cache(ttl_ms: Option<u64>) -> ToolCache {
+        ToolCache::new(ToolCacheConfig {
+            tools: HashMap::from([("read_file".to_string(), CachedTool { ttl_ms })]),
+            store_id: None,
+        })
+    }
+
+    fn response(result: Value) -> McpResponse {
+        McpResponse {
+            jsonrpc: "2.0".to_string(),
+            id: "1".to_string(),
+            result: Some(result),
+            error: None,
+        }
+    }
+
+    #[test]
+    fn canonical_json_ignores_key_order() {
+        let a = json!({ "path": "a.rs", "options": { "lines": 10, "from": 1 } });
+        let b = json!({ "options": { "from": 1, "lines": 10 }, "path": "a.rs" });
+        assert_eq!(canonical_json(&a), canonical_json(&b));
+        assert_ne!(
+            canonical_json(&json!([1, 2])),
+            canonical_json(&json!([2, 1]))
+        );
+    }
+
+    #[test]
+    fn hits_with_the_same_args_in_any_key_order() {
+        let mut cache = cache(None);
+        let args = json!({ "path": "a.rs", "encoding": "utf-8" });
+        let cached = response(json!({ "content": [] }));
+        cache.insert("fs", "read_file", &args, &cached, 0);
+
+        let reordered = json!({ "encoding": "utf-8", "path": "a.rs" });
+        let hit = cache.lookup("fs", "read_file", &reordered, 1_000_000);
+        assert_eq!(hit.unwrap().result, cached.result);
+        assert!(
+            cache
+                .lookup("fs", "read_file", &json!({ "path": "b.rs" }), 0)
+                .is_none()
+        );
+        assert!(cache.lookup("git", "read_file", &args, 0).is_none());
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (1, 2, 1));
+    }
+
+    #[test]
+    fn entries_expire_after_their_ttl() {
+        let mut cache = cache(Some(100));
+        let args = json!({ "path": "a.rs" });
+        cache.insert("fs", "read_file", &args, &response(json!({})), 1_000);
+        assert!(cache.lookup("fs", "read_file", &args, 1_099).is_some());
+        assert!(cache.lookup("fs", "read_file", &args, 1_100).is_none());
+        // The expired entry is dropped
+        assert_eq!(cache.stats().entries, 0);
+    }
+
+    #[test]
+    fn errors_and_other_tools_are_not_cached() {
+        let mut cache = cache(None);
+        let args = json!({ "path": "a.rs" });
+        cache.insert(
+            "fs",
+            "read_file",
+            &args,
+            &response(json!({ "content": [], "isError": true })),
+            0,
+        );
+        let mut failed = response(json!({}));
+        failed.result = None;
+        failed.error = Some(McpError {
+            code: -32603,
+            message: "boom".to_string(),
+            data: None,
+        });
+        cache.insert("fs", "read_file", &args, &failed, 0);
+        cache.insert("fs", "write_file", &args, &response(json!({})), 0);
+        assert_eq!(cache.stats().entries, 0);
+
+        // Lookups for tools that aren't cached don't count as misses
+        assert!(cache.lookup("fs", "write_file", &args, 0).is_none());
+        assert_eq!(cache.stats().misses, 0);
+    }
+}
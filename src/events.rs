@@ -0,0 +1,59 @@
+use crate::TaskStatus;
+use crate::metadata::TaskMetadata;
+use genai_types::{Message, MessageContent};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Events describing the progress of a task, streamed to live viewers
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TaskEvent {
+    MessageAdded {
+        index: usize,
+        message: Message,
+    },
+    ToolCall {
+        id: String,
+        name: String,
+        input: Value,
+    },
+    /// A tool call in supervised mode, waiting for `ApproveToolCall` or
+    /// `RejectToolCall`
+    ToolCallPending {
+        request_id: String,
+        name: String,
+        input: Value,
+    },
+    StatusChanged {
+        status: TaskStatus,
+    },
+    TaskCompleted,
+}
+
+/// An event as sent to viewers, tagged with the task it belongs to
+#[derive(Serialize, Debug)]
+pub struct TaggedEvent<'a> {
+    #[serde(flatten)]
+    pub event: &'a TaskEvent,
+    pub task: &'a TaskMetadata,
+}
+
+/// Builds the events for a message newly seen in the transcript
+pub fn message_events(index: usize, message: &Message) -> Vec<TaskEvent> {
+    let mut events = vec![TaskEvent::MessageAdded {
+        index,
+        message: message.clone(),
+    }];
+
+    for content in &message.content {
+        if let MessageContent::ToolUse { id, name, input } = content {
+            events.push(TaskEvent::ToolCall {
+                id: id.clone(),
+                name: name.clone(),
+                input: input.clone(),
+            });
+        }
+    }
+
+    events
+}
@@ -0,0 +1,72 @@
+use crate::mcp::SharedMcpServer;
+use crate::metadata::TaskMetadata;
+use genai_types::{Message, MessageContent};
+use serde_json::{Map, Value};
+
+/// Parent config keys a fork never inherits: it gets its own id and HTTP
+/// port, starts from the parent's transcript instead of the first message,
+/// and runs for real even if the parent is a replay
+const NOT_INHERITED: &[&str] = &["task_id", "initial_message", "history", "http", "replay"];
+
+/// Builds the config of a task that starts from the first `at_message_index`
+/// messages of `transcript`. `overrides` replace the parent's top-level keys.
+pub fn fork_config(
+    parent_config: &Value,
+    metadata: &TaskMetadata,
+    mcp_pool: &[SharedMcpServer],
+    transcript: &[Message],
+    at_message_index: usize,
+    overrides: Option<&Value>,
+) -> Result<Value, String> {
+    let history = transcript.get(..at_message_index).ok_or_else(|| {
+        format!(
+            "Can't fork at message {}, the transcript has {} messages",
+            at_message_index,
+            transcript.len()
+        )
+    })?;
+    if let Some(last) = history.last()
+        && last
+            .content
+            .iter()
+            .any(|content| matches!(content, MessageContent::ToolUse { .. }))
+    {
+        return Err(format!(
+            "Can't fork at message {}, it would separate a tool call from its result",
+            at_message_index
+        ));
+    }
+
+    let overrides = match overrides {
+        Some(Value::Object(overrides)) => overrides.clone(),
+        Some(Value::Null) | None => Map::new(),
+        Some(_) => return Err("Fork overrides must be an object".to_string()),
+    };
+
+    let mut config = parent_config.as_object().cloned().unwrap_or_default();
+    config.retain(|_, value| !value.is_null());
+    for key in NOT_INHERITED {
+        config.remove(*key);
+    }
+    // An explicit proxy takes precedence over a preset, so a new preset
+    // has to replace it
+    if overrides.contains_key("model") && !overrides.contains_key("model_proxy") {
+        config.remove("model_proxy");
+    }
+
+    // The parent's pool is already running and is handed over by actor id
+    if !mcp_pool.is_empty() {
+        config.insert(
+            "shared_mcp_servers".to_string(),
+            serde_json::json!(mcp_pool),
+        );
+    }
+    config.insert(
+        "parent_task_id".to_string(),
+        Value::from(metadata.task_id.clone()),
+    );
+    config.insert("history".to_string(), serde_json::json!(history));
+    config.extend(overrides);
+
+    Ok(Value::Object(config))
+}
@@ -0,0 +1,257 @@
+use crate::bindings::theater::simple::http_framework::{
+    add_route, create_server, enable_websocket, register_handler, send_websocket_message,
+    start_server as start_http_server,
+};
+use crate::bindings::theater::simple::http_types::{HttpRequest, HttpResponse, ServerConfig};
+use crate::bindings::theater::simple::websocket_types::{MessageType, WebsocketMessage};
+use crate::events::{TaggedEvent, TaskEvent};
+use crate::logging::{info, warn};
+use crate::metadata::TaskMetadata;
+use crate::{TaskManagerRequest, TaskManagerResponse};
+use genai_types::{Message, MessageContent, messages::Role};
+use serde::{Deserialize, Serialize};
+use serde_json::{from_slice, to_vec};
+
+const API_HANDLER_NAME: &str = "task-api";
+const EVENTS_HANDLER_NAME: &str = "task-events";
+
+/// Routes served by the HTTP front end, as (method, path)
+const API_ROUTES: &[(&str, &str)] = &[
+    ("POST", "/messages"),
+    ("POST", "/reopen"),
+    ("POST", "/start"),
+    ("GET", "/status"),
+    ("GET", "/transcript"),
+    ("POST", "/cancel"),
+    ("GET", "/tool-calls"),
+    ("POST", "/tool-calls/approve"),
+    ("POST", "/tool-calls/reject"),
+];
+
+/// Configuration for the optional HTTP front end
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HttpConfig {
+    pub port: u16,
+    pub host: Option<String>,
+    /// Path of the WebSocket live view, disabled unless set (e.g. "/events")
+    pub websocket_path: Option<String>,
+}
+
+/// Identifiers of the running HTTP server, kept in the task manager state
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HttpServerState {
+    pub server_id: u64,
+    pub api_handler_id: u64,
+    pub events_handler_id: Option<u64>,
+    pub port: u16,
+    pub connections: Vec<u64>,
+}
+
+impl HttpServerState {
+    /// Whether WebSocket viewers can connect to receive events
+    pub fn live_view(&self) -> bool {
+        self.events_handler_id.is_some()
+    }
+
+    /// Sends an event to every connected WebSocket client
+    pub fn broadcast(&self, event: &TaskEvent, metadata: &TaskMetadata) {
+        if self.connections.is_empty() {
+            return;
+        }
+
+        let message = event_message(event, metadata);
+        for connection_id in &self.connections {
+            if let Err(e) = send_websocket_message(self.server_id, *connection_id, &message) {
+                warn(&format!(
+                    "Failed to send event to WebSocket connection {}: {}",
+                    connection_id, e
+                ));
+            }
+        }
+    }
+
+    pub fn send_event(&self, connection_id: u64, event: &TaskEvent, metadata: &TaskMetadata) {
+        let message = event_message(event, metadata);
+        if let Err(e) = send_websocket_message(self.server_id, connection_id, &message) {
+            warn(&format!(
+                "Failed to send event to WebSocket connection {}: {}",
+                connection_id, e
+            ));
+        }
+    }
+}
+
+pub fn start_server(config: &HttpConfig) -> Result<HttpServerState, String> {
+    info(&format!("Starting HTTP front end on port {}", config.port));
+
+    let server_id = create_server(&ServerConfig {
+        port: Some(config.port),
+        host: config.host.clone(),
+        tls_config: None,
+    })
+    .map_err(|e| format!("Failed to create HTTP server: {}", e))?;
+
+    let api_handler_id = register_handler(API_HANDLER_NAME)
+        .map_err(|e| format!("Failed to register HTTP handler: {}", e))?;
+
+    for (method, path) in API_ROUTES {
+        add_route(server_id, path, method, api_handler_id)
+            .map_err(|e| format!("Failed to add route {} {}: {}", method, path, e))?;
+    }
+
+    let events_handler_id = match &config.websocket_path {
+        Some(path) => {
+            let handler_id = register_handler(EVENTS_HANDLER_NAME)
+                .map_err(|e| format!("Failed to register WebSocket handler: {}", e))?;
+            enable_websocket(
+                server_id,
+                path,
+                Some(handler_id),
+                handler_id,
+                Some(handler_id),
+            )
+            .map_err(|e| format!("Failed to enable WebSocket on {}: {}", path, e))?;
+            info(&format!("WebSocket live view enabled on {}", path));
+            Some(handler_id)
+        }
+        None => None,
+    };
+
+    let port =
+        start_http_server(server_id).map_err(|e| format!("Failed to start HTTP server: {}", e))?;
+
+    info(&format!("HTTP front end listening on port {}", port));
+    Ok(HttpServerState {
+        server_id,
+        api_handler_id,
+        events_handler_id,
+        port,
+        connections: vec![],
+    })
+}
+
+/// Maps an incoming HTTP request onto the task manager protocol.
+/// Requests that cannot be mapped produce the HTTP response to send back.
+pub fn parse_request(request: &HttpRequest) -> Result<TaskManagerRequest, HttpResponse> {
+    let path = request.uri.split('?').next().unwrap_or_default();
+
+    match (request.method.as_str(), path) {
+        ("POST", "/messages") => {
+            let body = request.body.as_deref().unwrap_or_default();
+            match from_slice::<Message>(body) {
+                Ok(message) => Ok(TaskManagerRequest::AddMessage { message }),
+                Err(e) => Err(error_response(400, format!("Invalid message body: {}", e))),
+            }
+        }
+        ("POST", "/reopen") => {
+            let body = request.body.as_deref().unwrap_or_default();
+            match from_slice::<Message>(body) {
+                Ok(message) => Ok(TaskManagerRequest::Reopen { message }),
+                Err(e) => Err(error_response(400, format!("Invalid message body: {}", e))),
+            }
+        }
+        ("POST", "/start") => Ok(TaskManagerRequest::StartChat),
+        ("GET", "/status") => Ok(TaskManagerRequest::GetStatus),
+        ("GET", "/transcript") => Ok(TaskManagerRequest::GetTranscript),
+        ("POST", "/cancel") => Ok(TaskManagerRequest::Cancel),
+        ("GET", "/tool-calls") => Ok(TaskManagerRequest::GetPendingToolCalls),
+        ("POST", "/tool-calls/approve") => {
+            parse_tool_call_decision(request).map(|decision| TaskManagerRequest::ApproveToolCall {
+                request_id: decision.request_id,
+            })
+        }
+        ("POST", "/tool-calls/reject") => {
+            parse_tool_call_decision(request).map(|decision| TaskManagerRequest::RejectToolCall {
+                request_id: decision.request_id,
+                reason: decision.reason,
+            })
+        }
+        (method, path) => Err(error_response(
+            404,
+            format!("No route for {} {}", method, path),
+        )),
+    }
+}
+
+/// Body of `POST /tool-calls/approve` and `/reject`
+#[derive(Deserialize)]
+struct ToolCallDecision {
+    request_id: String,
+    reason: Option<String>,
+}
+
+fn parse_tool_call_decision(request: &HttpRequest) -> Result<ToolCallDecision, HttpResponse> {
+    let body = request.body.as_deref().unwrap_or_default();
+    from_slice::<ToolCallDecision>(body)
+        .map_err(|e| error_response(400, format!("Invalid tool call decision: {}", e)))
+}
+
+/// Turns a message from a WebSocket client into a user message.
+/// Clients may send either a full `Message` as JSON or plain text.
+pub fn parse_client_message(message: &WebsocketMessage) -> Option<Message> {
+    let text = match (&message.ty, &message.text, &message.data) {
+        (MessageType::Text, Some(text), _) => text.clone(),
+        (MessageType::Binary, _, Some(data)) => String::from_utf8_lossy(data).into_owned(),
+        _ => return None,
+    };
+
+    match serde_json::from_str::<Message>(&text) {
+        Ok(message) => Some(message),
+        Err(_) => Some(Message {
+            role: Role::User,
+            content: vec![MessageContent::Text { text }],
+        }),
+    }
+}
+
+pub fn response_message(response: &TaskManagerResponse) -> WebsocketMessage {
+    text_message(serde_json::to_string(response).unwrap_or_default())
+}
+
+fn event_message(event: &TaskEvent, metadata: &TaskMetadata) -> WebsocketMessage {
+    let tagged = TaggedEvent {
+        event,
+        task: metadata,
+    };
+    text_message(serde_json::to_string(&tagged).unwrap_or_default())
+}
+
+fn text_message(text: String) -> WebsocketMessage {
+    WebsocketMessage {
+        ty: MessageType::Text,
+        data: None,
+        text: Some(text),
+    }
+}
+
+/// Status of an error answering `request`. Requests that only make sense in
+/// some task states fail with a client error; other failures are ours.
+pub fn error_status(request: &TaskManagerRequest) -> u16 {
+    match request {
+        TaskManagerRequest::Reopen { .. } => 409,
+        TaskManagerRequest::ApproveToolCall { .. } | TaskManagerRequest::RejectToolCall { .. } => {
+            404
+        }
+        _ => 500,
+    }
+}
+
+pub fn into_response(response: &TaskManagerResponse, error_status: u16) -> HttpResponse {
+    let status = match response {
+        TaskManagerResponse::Error { .. } => error_status,
+        _ => 200,
+    };
+    json_response(status, response)
+}
+
+pub fn error_response(status: u16, message: String) -> HttpResponse {
+    json_response(status, &TaskManagerResponse::Error { message })
+}
+
+fn json_response(status: u16, response: &TaskManagerResponse) -> HttpResponse {
+    HttpResponse {
+        status,
+        headers: vec![("Content-Type".to_string(), "application/json".to_string())],
+        body: Some(to_vec(response).unwrap_or_default()),
+    }
+}
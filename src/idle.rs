@@ -0,0 +1,42 @@
+use genai_types::{Message, MessageContent, messages::Role};
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_MAX_NUDGES: u32 = 2;
+const DEFAULT_NUDGE_MESSAGE: &str = "You stopped without calling task_complete. \
+    Continue working on the task, or call task_complete if it is done.";
+
+/// What to do when the model stops without calling `task_complete`
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct IdleConfig {
+    /// Nudges sent before the task is marked stalled
+    pub max_nudges: Option<u32>,
+    /// Text of the user message sent as a nudge
+    pub nudge_message: Option<String>,
+}
+
+impl IdleConfig {
+    pub fn max_nudges(&self) -> u32 {
+        self.max_nudges.unwrap_or(DEFAULT_MAX_NUDGES)
+    }
+
+    pub fn nudge_message(&self) -> String {
+        self.nudge_message
+            .clone()
+            .unwrap_or_else(|| DEFAULT_NUDGE_MESSAGE.to_string())
+    }
+}
+
+/// The model is done with its turn: the last message is from the assistant
+/// and doesn't call a tool
+pub fn is_idle(transcript: &[Message]) -> bool {
+    match transcript.last() {
+        Some(message) => {
+            matches!(message.role, Role::Assistant)
+                && !message
+                    .content
+                    .iter()
+                    .any(|content| matches!(content, MessageContent::ToolUse { .. }))
+        }
+        None => false,
+    }
+}
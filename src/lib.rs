@@ -1,23 +1,57 @@
+mod audit;
 #[allow(warnings)]
 mod bindings;
+mod cache;
+mod events;
+mod fork;
+mod http;
+mod idle;
+mod limits;
+mod logging;
+mod mcp;
+mod metadata;
+mod models;
+mod notify;
+mod prompt;
 mod protocol;
+mod proxy;
+mod race;
+mod replay;
+mod samples;
+mod schema;
+mod secrets;
+mod verifier;
 
 use bindings::exports::theater::simple::actor::Guest;
+use bindings::exports::theater::simple::http_handlers::Guest as HttpHandlers;
 use bindings::exports::theater::simple::message_server_client::Guest as MessageServerClient;
 use bindings::exports::theater::simple::supervisor_handlers::Guest as SupervisorHandlers;
-use bindings::theater::simple::message_server_host::send;
-use bindings::theater::simple::runtime::{log, shutdown};
-use bindings::theater::simple::supervisor::spawn;
+use bindings::theater::simple::http_framework::HandlerId;
+use bindings::theater::simple::http_types::{HttpRequest, HttpResponse, MiddlewareResult};
+use bindings::theater::simple::message_server_host::{request, respond_to_request, send};
+use bindings::theater::simple::runtime::shutdown;
+use bindings::theater::simple::supervisor::{spawn, stop_child};
+use bindings::theater::simple::timing::now;
 use bindings::theater::simple::types::{ChannelAccept, ChannelId, WitActorError};
+use bindings::theater::simple::websocket_types::WebsocketMessage;
+use events::TaskEvent;
 use genai_types::{Message, MessageContent, messages::Role};
-use protocol::{ActorMcpConfig, McpConfig, McpServer};
+use logging::{LogLevel, debug, error, info, warn};
+use metadata::TaskMetadata;
+use models::{ModelPresets, ModelTurn};
+use protocol::{
+    ActorMcpConfig, ChatStateRequest, ChatStateResponse, McpActorRequest, McpConfig, McpServer,
+};
 use serde::{Deserialize, Serialize};
 use serde_json::{Value, from_slice, to_vec};
+use std::collections::HashMap;
 
 struct Component;
 
 const CHAT_STATE_MANIFEST_PATH: &str =
     "/Users/colinrozzi/work/actor-registry/chat-state/manifest.toml";
+const TASK_MANAGER_MANIFEST_PATH: &str =
+    "/Users/colinrozzi/work/actor-registry/task-manager/manifest.toml";
 const TASK_MONITOR_MANIFEST_PATH: &str =
     "https://github.com/colinrozzi/task-monitor-mcp-actor/releases/latest/download/manifest.toml";
 
@@ -26,35 +60,190 @@ const TASK_MONITOR_MANIFEST_PATH: &str =
 #[serde(tag = "type")]
 enum TaskManagerRequest {
     GetChatStateActorId,
-    AddMessage { message: Message },
+    AddMessage {
+        message: Message,
+    },
     StartChat,
+    GetStatus,
+    GetTranscript,
+    GetSystemPrompt,
+    GetMcpPool,
+    GetAuditLog,
+    AddMcpServer {
+        server: McpServer,
+    },
+    RemoveMcpServer {
+        name: String,
+    },
+    ExportAudit {
+        store_id: Option<String>,
+    },
+    Reopen {
+        message: Message,
+    },
+    Fork {
+        at_message_index: usize,
+        overrides: Option<Value>,
+        /// Return the fork's config without spawning it
+        #[serde(default)]
+        config_only: bool,
+    },
+    GetPendingToolCalls,
+    ApproveToolCall {
+        request_id: String,
+    },
+    RejectToolCall {
+        request_id: String,
+        reason: Option<String>,
+    },
+    Cancel,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(tag = "type")]
 enum TaskManagerResponse {
-    ChatStateActorId { actor_id: String },
+    ChatStateActorId {
+        actor_id: String,
+    },
+    Status(Box<StatusReport>),
+    Transcript {
+        messages: Vec<Message>,
+        model_turns: Vec<ModelTurn>,
+    },
+    SystemPrompt {
+        system_prompt: String,
+    },
+    McpPool {
+        servers: Vec<mcp::SharedMcpServer>,
+    },
+    AuditLog {
+        entries: Vec<proxy::AuditEntry>,
+    },
+    AuditDocument {
+        document: audit::AuditDocument,
+    },
+    AuditStored {
+        store_id: String,
+        hash: String,
+    },
+    Forked {
+        actor_id: Option<String>,
+        config: Value,
+    },
+    PendingToolCalls {
+        calls: Vec<proxy::PendingCall>,
+    },
     Success,
-    Error { message: String },
+    Error {
+        message: String,
+    },
+}
+
+/// Where the task stands, as returned by `GetStatus`
+#[derive(Serialize, Deserialize, Debug)]
+struct StatusReport {
+    status: TaskStatus,
+    mode: TaskMode,
+    chat_state_actor_id: Option<String>,
+    model: String,
+    result: Option<Value>,
+    metadata: TaskMetadata,
+    completions: Vec<CompletionRecord>,
+    forks: Vec<String>,
+    race: Option<race::RaceState>,
+    samples: Option<samples::SampleState>,
+    tool_usage: Option<limits::ToolUsage>,
+    tool_cache: Option<cache::CacheStats>,
+}
+
+/// Lifecycle of the task driven by this manager
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum TaskStatus {
+    Pending,
+    Running,
+    WaitingForInput,
+    Stalled,
+    Completed,
+    Cancelled,
+    Failed,
+}
+
+/// How much the task runs on its own
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum TaskMode {
+    /// Runs until complete without waiting on a human
+    #[default]
+    Autonomous,
+    /// Pauses after each assistant turn until the next `AddMessage`
+    Interactive,
+    /// Autonomous, but every proxied tool call waits for approval
+    Supervised,
 }
 
 // Configuration for task manager
 #[derive(Serialize, Deserialize, Debug)]
 struct TaskManagerConfig {
     // Core task definition
-    system_prompt: Option<String>,
+    system_prompt: Option<prompt::SystemPromptConfig>,
     initial_message: Option<String>,
-
-    // AI configuration
+    // Transcript to start from, set on forks
+    history: Option<Vec<Message>>,
+
+    // Task metadata, used to link tasks across services; `task_id`
+    // defaults to the actor id
+    task_id: Option<String>,
+    title: Option<String>,
+    tags: Option<Vec<String>>,
+    owner: Option<String>,
+    parent_task_id: Option<String>,
+    correlation_id: Option<String>,
+
+    // AI configuration, a single proxy or an ordered fallback list
+    model: Option<String>,
+    model_presets_path: Option<String>,
     model_proxy: Option<Value>,
     temperature: Option<f64>,
     max_tokens: Option<u32>,
 
     // Tool configuration
     mcp_servers: Option<Vec<McpServer>>,
+    shared_mcp_servers: Option<Vec<mcp::SharedMcpServer>>,
+    // Route actor MCP servers through the task manager and audit the calls
+    mcp_proxy: Option<bool>,
+    tool_limits: Option<limits::ToolLimits>,
+    tool_cache: Option<cache::ToolCacheConfig>,
+
+    // Structured output, validated when the model completes the task
+    output_schema: Option<Value>,
+    output_max_retries: Option<u32>,
+
+    // Review of the work before the completion is accepted
+    verifier: Option<verifier::VerifierConfig>,
+
+    // Run the task on several models at once and keep one result
+    race: Option<race::RaceConfig>,
+    // Run the task several times and keep the majority answer
+    samples: Option<u32>,
+    sample_temperature: Option<samples::TemperatureRange>,
 
     // Execution mode
+    mode: Option<TaskMode>,
     auto_exit_on_completion: Option<bool>,
+    // Nudging the model when it stops without calling task_complete
+    idle: Option<idle::IdleConfig>,
+    // Actors and channels told when the task finishes
+    notify: Option<Vec<notify::NotifyTarget>>,
+
+    // HTTP front end, disabled unless configured
+    http: Option<http::HttpConfig>,
+
+    // Re-run a recorded task against its recorded model and tool output
+    replay: Option<replay::ReplayConfig>,
+
+    // Minimum log level and extra keys to redact from logged config
+    logging: Option<logging::LogConfig>,
 
     #[serde(flatten)]
     other: Value,
@@ -65,27 +254,140 @@ impl Default for TaskManagerConfig {
         Self {
             system_prompt: None,
             initial_message: None,
+            history: None,
+            task_id: None,
+            title: None,
+            tags: None,
+            owner: None,
+            parent_task_id: None,
+            correlation_id: None,
+            model: None,
+            model_presets_path: None,
             model_proxy: None,
             temperature: None,
             max_tokens: None,
             mcp_servers: None,
+            shared_mcp_servers: None,
+            mcp_proxy: None,
+            tool_limits: None,
+            tool_cache: None,
+            output_schema: None,
+            output_max_retries: None,
+            verifier: None,
+            race: None,
+            samples: None,
+            sample_temperature: None,
+            mode: None,
             auto_exit_on_completion: None,
+            idle: None,
+            notify: None,
+            http: None,
+            replay: None,
+            logging: None,
             other: serde_json::json!({}),
         }
     }
 }
 
+/// Sent by the task monitor when the model calls `task_complete`, carrying
+/// the model's result when the task has an output schema
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct TaskComplete {
+    result: Option<Value>,
+}
+
+impl TaskComplete {
+    fn parse(data: &[u8]) -> Option<Self> {
+        // The monitor sends a bare `null` when there is no result
+        if from_slice::<()>(data).is_ok() {
+            return Some(Self::default());
+        }
+        from_slice(data).ok()
+    }
+}
+
+/// Handed to our supervisor as shutdown data when the task ends
+#[derive(Serialize, Deserialize, Debug)]
+struct CompletionPayload {
+    metadata: TaskMetadata,
+    status: TaskStatus,
+    result: Option<Value>,
+    verdict: Option<verifier::Verdict>,
+    vote: Option<samples::Vote>,
+}
+
+/// An accepted result. A reopened task can complete several times, and
+/// each completion is kept.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct CompletionRecord {
+    result: Option<Value>,
+    verdict: Option<verifier::Verdict>,
+    vote: Option<samples::Vote>,
+    /// Milliseconds since the epoch
+    completed_at: u64,
+}
+
+const DEFAULT_OUTPUT_MAX_RETRIES: u32 = 3;
+const DEFAULT_VERIFIER_MAX_REJECTIONS: u32 = 2;
+
+/// Work the task manager schedules for itself by sending to its own mailbox,
+/// so long-running chat-state calls don't block the caller's request
 #[derive(Serialize, Deserialize, Debug)]
-struct TaskComplete;
+#[serde(tag = "type")]
+enum SelfMessage {
+    GenerateCompletion,
+    ReplayNext,
+    CheckIdle,
+}
 
 // State management
 #[derive(Serialize, Deserialize, Debug)]
 struct TaskManagerState {
     actor_id: String,
+    metadata: TaskMetadata,
     chat_state_actor_id: Option<String>,
     original_config: Value,
     initial_message: Option<String>,
     exit_on_completion: bool,
+    status: TaskStatus,
+    http_server: Option<http::HttpServerState>,
+    seen_messages: usize,
+    model_chain: Vec<Value>,
+    model_index: usize,
+    model_turns: Vec<ModelTurn>,
+    model_presets: ModelPresets,
+    output_schema: Option<Value>,
+    output_max_retries: u32,
+    output_attempts: u32,
+    result: Option<Value>,
+    verifier: Option<verifier::VerifierConfig>,
+    verifier_rejections: u32,
+    verdict: Option<verifier::Verdict>,
+    mcp_pool: Vec<mcp::SharedMcpServer>,
+    mcp_proxy: Option<proxy::McpProxy>,
+    /// A generation was requested without waiting while proxying, and the
+    /// model hasn't called `task_complete` since
+    proxied_turn: bool,
+    audit_log: Vec<proxy::AuditEntry>,
+    /// MCP actors spawned for servers added mid-task, by server name
+    owned_mcp_actors: HashMap<String, String>,
+    /// Children replaced or stopped during the task, as `(role, actor_id)`,
+    /// whose chains still belong in the audit
+    retired_children: Vec<(String, String)>,
+    replay: Option<replay::ReplayState>,
+    notify: Vec<notify::NotifyTarget>,
+    log_config: logging::LogConfig,
+    idle: Option<idle::IdleConfig>,
+    idle_nudges: u32,
+    mode: TaskMode,
+    completions: Vec<CompletionRecord>,
+    /// Our own config as received, which forks are derived from
+    task_config: Value,
+    history: Vec<Message>,
+    forks: Vec<String>,
+    race: Option<race::RaceState>,
+    samples: Option<samples::SampleState>,
 }
 
 impl TaskManagerState {
@@ -94,13 +396,92 @@ impl TaskManagerState {
         config: Value,
         initial_message: Option<String>,
         exit_on_completion: bool,
+        model_chain: Vec<Value>,
+        model_presets: ModelPresets,
     ) -> Self {
         Self {
+            metadata: TaskMetadata {
+                task_id: actor_id.clone(),
+                ..TaskMetadata::default()
+            },
             actor_id,
             chat_state_actor_id: None,
             original_config: config,
             initial_message,
             exit_on_completion,
+            status: TaskStatus::Pending,
+            http_server: None,
+            seen_messages: 0,
+            model_chain,
+            model_index: 0,
+            model_turns: vec![],
+            model_presets,
+            output_schema: None,
+            output_max_retries: DEFAULT_OUTPUT_MAX_RETRIES,
+            output_attempts: 0,
+            result: None,
+            verifier: None,
+            verifier_rejections: 0,
+            verdict: None,
+            mcp_pool: vec![],
+            mcp_proxy: None,
+            proxied_turn: false,
+            audit_log: vec![],
+            owned_mcp_actors: HashMap::new(),
+            retired_children: vec![],
+            replay: None,
+            notify: vec![],
+            log_config: logging::LogConfig::default(),
+            idle: None,
+            idle_nudges: 0,
+            mode: TaskMode::default(),
+            completions: vec![],
+            task_config: Value::Null,
+            history: vec![],
+            forks: vec![],
+            race: None,
+            samples: None,
+        }
+    }
+
+    /// Loads the state handed to a handler and applies its log config and
+    /// metadata to the handler's log lines
+    fn from_bytes(state_bytes: &[u8]) -> Result<Self, serde_json::Error> {
+        let state: Self = from_slice(state_bytes)?;
+        logging::set_context(&state.metadata, &state.log_config);
+        Ok(state)
+    }
+
+    fn current_model(&self) -> String {
+        self.model_chain
+            .get(self.model_index)
+            .map(models::model_label)
+            .unwrap_or_default()
+    }
+
+    /// The vote behind the result, when the task was sampled
+    fn current_vote(&self) -> Option<samples::Vote> {
+        self.samples
+            .as_ref()
+            .and_then(|samples| samples.vote.clone())
+    }
+
+    fn set_status(&mut self, status: TaskStatus) {
+        if self.status != status {
+            logging::event(
+                LogLevel::Info,
+                "status_changed",
+                &format!("Task status: {:?} -> {:?}", self.status, status),
+                serde_json::json!({ "from": self.status, "to": status }),
+            );
+            self.status = status;
+            self.emit(&TaskEvent::StatusChanged { status });
+        }
+    }
+
+    fn emit(&self, event: &TaskEvent) {
+        if let Some(server) = &self.http_server {
+            server.broadcast(event, &self.metadata);
         }
     }
 
@@ -117,61 +498,269 @@ impl TaskManagerState {
 
 impl Guest for Component {
     fn init(state: Option<Vec<u8>>, params: (String,)) -> Result<(Option<Vec<u8>>,), String> {
-        log("Task manager actor initializing...");
-        log(&format!("Received parameters: {:?}", params));
-        log(&format!(
-            "Initial state: {}",
-            String::from_utf8_lossy(&state.clone().unwrap_or_default())
-        ));
-
         let (self_id,) = params;
 
         // Parse initial configuration if provided
-        let (task_config, initial_message, exit_on_completion) = if let Some(state_bytes) = state {
+        let mut config = if let Some(state_bytes) = state {
             match from_slice::<TaskManagerConfig>(&state_bytes) {
                 Ok(config) => {
-                    log("Parsed initial configuration");
-                    let task_config = create_task_config(&self_id, &config);
-                    (
-                        task_config,
-                        config.initial_message,
-                        config.auto_exit_on_completion.unwrap_or(false),
-                    )
+                    debug("Parsed initial configuration");
+                    config
                 }
                 Err(e) => {
-                    log(&format!(
+                    warn(&format!(
                         "Failed to parse initial config, using defaults: {}",
                         e
                     ));
-                    let task_config = create_task_config(&self_id, &TaskManagerConfig::default());
-                    (task_config, None, false)
+                    TaskManagerConfig::default()
+                }
+            }
+        } else {
+            info("No initial state provided, using default configuration");
+            TaskManagerConfig::default()
+        };
+
+        // Kept for forks; a replay recording is never inherited, so it isn't kept
+        let mut fork_base = serde_json::to_value(&config).unwrap_or_default();
+        if let Some(fork_base) = fork_base.as_object_mut() {
+            fork_base.remove("replay");
+        }
+
+        let metadata = TaskMetadata {
+            task_id: config.task_id.clone().unwrap_or_else(|| self_id.clone()),
+            title: config.title.clone(),
+            tags: config.tags.clone().unwrap_or_default(),
+            owner: config.owner.clone(),
+            parent_task_id: config.parent_task_id.clone(),
+            correlation_id: config.correlation_id.clone(),
+        };
+        let log_config = config.logging.clone().unwrap_or_default();
+        logging::set_context(&metadata, &log_config);
+        info("Task manager actor initializing...");
+        if let Ok(config_value) = serde_json::to_value(&config) {
+            logging::event(
+                LogLevel::Debug,
+                "task_config",
+                "Received task config",
+                config_value,
+            );
+        }
+
+        // Tools and the verifier are never called in a replay; their output
+        // comes from the recording
+        if config.replay.is_some() {
+            info("Replay mode: MCP servers, proxy and verifier are disabled");
+            config.mcp_servers = None;
+            config.shared_mcp_servers = None;
+            config.mcp_proxy = None;
+            config.tool_limits = None;
+            config.tool_cache = None;
+            config.verifier = None;
+            config.race = None;
+            config.samples = None;
+            if config.mode == Some(TaskMode::Supervised) {
+                config.mode = Some(TaskMode::Autonomous);
+            }
+        }
+        let mode = config.mode.unwrap_or_default();
+        if config.race.is_some() && config.samples.is_some() {
+            let error_msg = "race and samples can't be combined".to_string();
+            error(&error_msg);
+            return Err(error_msg);
+        }
+
+        let model_presets = match models::load_presets(config.model_presets_path.as_deref()) {
+            Ok(presets) => presets,
+            Err(e) => {
+                let error_msg = format!("Failed to load model presets: {}", e);
+                error(&error_msg);
+                return Err(error_msg);
+            }
+        };
+
+        let model_chain = resolve_model_chain(&config, &model_presets)?;
+
+        // MCP servers learn which task they serve through their init state
+        for server in config.mcp_servers.iter_mut().flatten() {
+            metadata.add_to_server(server);
+        }
+
+        // Reject misconfigured MCP servers before chat-state tries to start them
+        if let Err(e) = mcp::validate_servers(config.mcp_servers.as_deref().unwrap_or_default()) {
+            let error_msg = format!("Invalid MCP server configuration: {}", e);
+            error(&error_msg);
+            return Err(error_msg);
+        }
+
+        // Start the shared MCP servers once, so every chat-state we spawn reuses them
+        let mcp_pool = match mcp::start_shared_servers(
+            config.shared_mcp_servers.as_deref().unwrap_or_default(),
+        ) {
+            Ok(pool) => pool,
+            Err(e) => {
+                let error_msg = format!("Failed to start shared MCP servers: {}", e);
+                error(&error_msg);
+                return Err(error_msg);
+            }
+        };
+
+        // Limits and caching only apply to calls that pass through us
+        if !config.mcp_proxy.unwrap_or(false) {
+            let proxy_options = [
+                ("tool_limits", config.tool_limits.is_some()),
+                ("tool_cache", config.tool_cache.is_some()),
+                ("supervised mode", mode == TaskMode::Supervised),
+            ];
+            if let Some((option, _)) = proxy_options.iter().find(|(_, set)| *set) {
+                let error_msg = format!("{} requires mcp_proxy to be enabled", option);
+                error(&error_msg);
+                return Err(error_msg);
+            }
+        }
+
+        // Calls to stdio servers never pass through us, so they can't be held
+        if mode == TaskMode::Supervised
+            && config
+                .mcp_servers
+                .iter()
+                .flatten()
+                .any(|server| matches!(server.config, McpConfig::StdPipe(_)))
+        {
+            let error_msg = "supervised mode can't be used with stdio MCP servers".to_string();
+            error(&error_msg);
+            return Err(error_msg);
+        }
+
+        // Generation isn't waited on when proxying, so the end of a turn is
+        // never seen: an interactive task would never wait for input, idle
+        // checks never run and provider errors never trigger a fallback. The
+        // verifier reads the transcript while the model's turn may still be
+        // calling tools through us, which would deadlock.
+        if config.mcp_proxy.unwrap_or(false) {
+            let proxy_conflicts = [
+                ("interactive mode", mode == TaskMode::Interactive),
+                ("idle", config.idle.is_some()),
+                ("model fallback", model_chain.len() > 1),
+                ("verifier", config.verifier.is_some()),
+            ];
+            if let Some((option, _)) = proxy_conflicts.iter().find(|(_, set)| *set) {
+                let error_msg = format!("{} can't be used with mcp_proxy", option);
+                error(&error_msg);
+                return Err(error_msg);
+            }
+        }
+
+        // Interactive tasks stay up for the human after completing
+        if mode == TaskMode::Interactive && config.auto_exit_on_completion == Some(true) {
+            warn("auto_exit_on_completion is ignored in interactive mode");
+        }
+
+        // Put ourselves in front of the actor MCP servers if proxying is enabled
+        let mcp_proxy = if config.mcp_proxy.unwrap_or(false) {
+            match proxy::McpProxy::start(
+                config.mcp_servers.as_deref().unwrap_or_default(),
+                &mcp_pool,
+                config.tool_limits.clone().unwrap_or_default(),
+                config.tool_cache.clone(),
+                mode == TaskMode::Supervised,
+            ) {
+                Ok(mcp_proxy) => Some(mcp_proxy),
+                Err(e) => {
+                    let error_msg = format!("Failed to start MCP proxy: {}", e);
+                    error(&error_msg);
+                    return Err(error_msg);
                 }
             }
         } else {
-            log("No initial state provided, using default configuration");
-            let task_config = create_task_config(&self_id, &TaskManagerConfig::default());
-            (task_config, None, false)
+            None
         };
 
-        log(&format!("Using task config: {}", task_config));
+        let task_config = match create_task_config(
+            &self_id,
+            &config,
+            &metadata,
+            &model_presets,
+            &mcp_pool,
+            mcp_proxy.is_some(),
+        ) {
+            Ok(task_config) => task_config,
+            Err(e) => {
+                let error_msg = format!("Failed to create task config: {}", e);
+                error(&error_msg);
+                return Err(error_msg);
+            }
+        };
+        logging::event(
+            LogLevel::Debug,
+            "chat_state_config",
+            "Using chat state config",
+            task_config.clone(),
+        );
 
         // Create our state
         let mut task_state = TaskManagerState::new(
             self_id,
             task_config.clone(),
-            initial_message,
-            exit_on_completion,
+            config.initial_message,
+            config.auto_exit_on_completion.unwrap_or(false) && mode != TaskMode::Interactive,
+            model_chain,
+            model_presets,
         );
+        task_state.output_schema = config.output_schema;
+        task_state.output_max_retries = config
+            .output_max_retries
+            .unwrap_or(DEFAULT_OUTPUT_MAX_RETRIES);
+        task_state.verifier = config.verifier;
+        task_state.mcp_pool = mcp_pool;
+        task_state.mcp_proxy = mcp_proxy;
+        task_state.metadata = metadata;
+        task_state.notify = config.notify.unwrap_or_default();
+        task_state.log_config = log_config;
+        task_state.idle = config.idle;
+        task_state.mode = mode;
+        task_state.task_config = fork_base;
+        task_state.history = config.history.unwrap_or_default();
+        task_state.race = config.race.map(race::RaceState::new);
+        task_state.samples = config
+            .samples
+            .map(|count| samples::SampleState::new(count, config.sample_temperature));
 
         // Spawn the chat-state actor with the task config
         match spawn_chat_state_actor(&task_config) {
             Ok(chat_actor_id) => {
-                log(&format!("Chat state actor spawned: {}", chat_actor_id));
+                logging::event(
+                    LogLevel::Info,
+                    "chat_state_spawned",
+                    "Chat state actor spawned",
+                    serde_json::json!({ "chat_state_actor_id": chat_actor_id }),
+                );
                 task_state.set_chat_state_actor_id(chat_actor_id);
             }
             Err(e) => {
                 let error_msg = format!("Failed to spawn chat state actor: {}", e);
-                log(&error_msg);
+                error(&error_msg);
+                return Err(error_msg);
+            }
+        }
+
+        // Start the HTTP front end if configured
+        if let Some(http_config) = &config.http {
+            match http::start_server(http_config) {
+                Ok(server) => task_state.http_server = Some(server),
+                Err(e) => {
+                    let error_msg = format!("Failed to start HTTP front end: {}", e);
+                    error(&error_msg);
+                    return Err(error_msg);
+                }
+            }
+        }
+
+        // Start feeding the recorded inputs back in once init is done
+        if let Some(replay_config) = &config.replay {
+            task_state.replay = Some(replay::ReplayState::from_events(&replay_config.events));
+            if let Err(e) = send_to_self(&task_state, &SelfMessage::ReplayNext) {
+                let error_msg = format!("Failed to start replay: {}", e);
+                error(&error_msg);
                 return Err(error_msg);
             }
         }
@@ -180,7 +769,7 @@ impl Guest for Component {
         let state_bytes =
             to_vec(&task_state).map_err(|e| format!("Failed to serialize task state: {}", e))?;
 
-        log("Task manager actor initialized successfully");
+        info("Task manager actor initialized successfully");
         Ok((Some(state_bytes),))
     }
 }
@@ -190,9 +779,22 @@ impl SupervisorHandlers for Component {
         state: Option<Vec<u8>>,
         params: (String, WitActorError),
     ) -> Result<(Option<Vec<u8>>,), String> {
-        let (_child_id, _error) = params;
-        log("Task manager: Child actor error occurred");
-        Ok((state,))
+        let (child_id, _error) = params;
+        warn(&format!(
+            "Task manager: Child actor error occurred: {}",
+            child_id
+        ));
+
+        let Some(mut task_state) = state
+            .as_deref()
+            .and_then(|state_bytes| TaskManagerState::from_bytes(state_bytes).ok())
+        else {
+            return Ok((state,));
+        };
+        child_gone(&mut task_state, &child_id);
+
+        let updated_state_bytes = to_vec(&task_state).unwrap_or_default();
+        Ok((Some(updated_state_bytes),))
     }
 
     fn handle_child_exit(
@@ -200,28 +802,54 @@ impl SupervisorHandlers for Component {
         params: (String, Option<Vec<u8>>),
     ) -> Result<(Option<Vec<u8>>,), String> {
         let (child_id, _exit_data) = params;
-        log(&format!("Task manager: Child actor exited: {}", child_id));
+        warn(&format!("Task manager: Child actor exited: {}", child_id));
 
-        let task_state: TaskManagerState = match state {
-            Some(state_bytes) => match from_slice(&state_bytes) {
+        let mut task_state: TaskManagerState = match state {
+            Some(state_bytes) => match TaskManagerState::from_bytes(&state_bytes) {
                 Ok(state) => state,
                 Err(e) => {
-                    log(&format!("Failed to deserialize task state: {}", e));
+                    warn(&format!("Failed to deserialize task state: {}", e));
                     return Ok((None,));
                 }
             },
             None => {
-                log("No state available for child exit handler");
+                warn("No state available for child exit handler");
                 return Ok((None,));
             }
         };
 
-        // If our chat state actor exited, we should probably shut down too
-        if let Ok(chat_actor_id) = task_state.get_chat_state_actor_id() {
-            if chat_actor_id == &child_id {
-                log("Chat state actor exited, shutting down task manager");
-                let _ = shutdown(None);
+        // Without our chat state actor the task can't go on, so it fails
+        if task_state
+            .get_chat_state_actor_id()
+            .is_ok_and(|chat_actor_id| chat_actor_id == &child_id)
+        {
+            warn("Chat state actor exited, shutting down task manager");
+            // A task that already ended has been reported
+            if !matches!(
+                task_state.status,
+                TaskStatus::Completed | TaskStatus::Cancelled | TaskStatus::Failed
+            ) {
+                task_state.set_status(TaskStatus::Failed);
+                notify_finished(&task_state);
             }
+            shutdown_with_payload(&task_state);
+        }
+
+        if task_state.forks.contains(&child_id) {
+            info(&format!("Fork {} exited", child_id));
+        }
+        child_gone(&mut task_state, &child_id);
+
+        // Shared MCP servers aren't restarted; chat-state will report the failed tool calls
+        if let Some(server) = task_state
+            .mcp_pool
+            .iter()
+            .find(|server| server.actor_id.as_deref() == Some(child_id.as_str()))
+        {
+            warn(&format!(
+                "Shared MCP server {} ({}) exited",
+                server.name, child_id
+            ));
         }
 
         let updated_state_bytes = to_vec(&task_state).unwrap_or_default();
@@ -233,7 +861,7 @@ impl SupervisorHandlers for Component {
         params: (String,),
     ) -> Result<(Option<Vec<u8>>,), String> {
         let (child_id,) = params;
-        log(&format!(
+        info(&format!(
             "Task manager: Child actor externally stopped: {}",
             child_id
         ));
@@ -247,39 +875,73 @@ impl MessageServerClient for Component {
         params: (Vec<u8>,),
     ) -> Result<(Option<Vec<u8>>,), String> {
         let (data,) = params;
-        log("Task manager handling send message");
+        debug("Task manager handling send message");
 
-        let parsed_state: TaskManagerState = match state {
-            Some(state_bytes) => match from_slice(&state_bytes) {
+        let mut parsed_state: TaskManagerState = match state {
+            Some(state_bytes) => match TaskManagerState::from_bytes(&state_bytes) {
                 Ok(state) => state,
                 Err(e) => {
                     let error_msg = format!("Failed to deserialize task state: {}", e);
-                    log(&error_msg);
+                    error(&error_msg);
                     return Err(error_msg);
                 }
             },
             None => {
                 let error_msg = "No state available for send_message".to_string();
-                log(&error_msg);
+                error(&error_msg);
                 return Err(error_msg);
             }
         };
 
+        // Work we scheduled for ourselves is handled here and not forwarded
+        if let Ok(self_message) = from_slice::<SelfMessage>(&data) {
+            match self_message {
+                SelfMessage::GenerateCompletion => {
+                    if let Err(e) = generate_completion(&mut parsed_state) {
+                        warn(&format!("Completion generation failed: {}", e));
+                    }
+                }
+                SelfMessage::ReplayNext => {
+                    if let Err(e) = replay_next(&mut parsed_state) {
+                        warn(&format!("Replay failed: {}", e));
+                    }
+                }
+                SelfMessage::CheckIdle => {
+                    if let Err(e) = check_idle(&mut parsed_state) {
+                        warn(&format!("Idle check failed: {}", e));
+                    }
+                }
+            }
+
+            let state_bytes = to_vec(&parsed_state).unwrap_or_default();
+            return Ok((Some(state_bytes),));
+        }
+
+        // Racers and samples report back to us when they finish
+        if (parsed_state.race.is_some() || parsed_state.samples.is_some())
+            && let Ok(notification) = from_slice::<notify::Notification>(&data)
+        {
+            handle_child_notification(&mut parsed_state, notification);
+            let state_bytes = to_vec(&parsed_state).unwrap_or_default();
+            return Ok((Some(state_bytes),));
+        }
+
         // If the message is a Task Complete message, handle it here.
         // Otherwise, pass it along to the chat state actor.
-        match from_slice::<TaskComplete>(&data) {
-            Ok(_) => {
-                log("Received TaskComplete message, handling completion");
-                // If auto exit is enabled, shutdown the task manager
-                if parsed_state.exit_on_completion {
-                    log("Auto exit on completion is enabled, shutting down task manager");
-                    let _ = shutdown(None);
-                } else {
-                    log("Task completed, but auto exit is disabled");
+        match TaskComplete::parse(&data) {
+            Some(completion) => {
+                info("Received TaskComplete message, handling completion");
+                // The model is done, so its turn makes no more tool calls
+                // and the messages it added can be synced
+                if parsed_state.proxied_turn {
+                    parsed_state.proxied_turn = false;
+                    let model = parsed_state.current_model();
+                    record_model_turns(&mut parsed_state, &model);
                 }
+                handle_task_complete(&mut parsed_state, completion);
             }
-            Err(_) => {
-                log("Received non-TaskComplete message, forwarding to chat state actor");
+            None => {
+                debug("Received non-TaskComplete message, forwarding to chat state actor");
             }
         }
 
@@ -287,17 +949,17 @@ impl MessageServerClient for Component {
         match parsed_state.get_chat_state_actor_id() {
             Ok(chat_actor_id) => match send(chat_actor_id, &data) {
                 Ok(_) => {
-                    log("Message forwarded to chat state actor");
+                    debug("Message forwarded to chat state actor");
                 }
                 Err(e) => {
                     let error_msg = format!("Failed to forward message: {:?}", e);
-                    log(&error_msg);
+                    error(&error_msg);
                     return Err(error_msg);
                 }
             },
             Err(e) => {
                 let error_msg = format!("Chat state actor not available: {}", e);
-                log(&error_msg);
+                error(&error_msg);
                 return Err(error_msg);
             }
         }
@@ -310,16 +972,16 @@ impl MessageServerClient for Component {
         state: Option<Vec<u8>>,
         params: (String, Vec<u8>),
     ) -> Result<(Option<Vec<u8>>, (Option<Vec<u8>>,)), String> {
-        let (_request_id, data) = params;
-        log("Task manager handling request message");
+        let (request_id, data) = params;
+        debug("Task manager handling request message");
 
         // Deserialize current state
-        let task_state: TaskManagerState = match state {
-            Some(state_bytes) => match from_slice(&state_bytes) {
+        let mut task_state: TaskManagerState = match state {
+            Some(state_bytes) => match TaskManagerState::from_bytes(&state_bytes) {
                 Ok(state) => state,
                 Err(e) => {
                     let error_msg = format!("Failed to deserialize task state: {}", e);
-                    log(&error_msg);
+                    error(&error_msg);
                     let error_response = TaskManagerResponse::Error { message: error_msg };
                     return Ok((None, (Some(to_vec(&error_response).unwrap_or_default()),)));
                 }
@@ -332,15 +994,57 @@ impl MessageServerClient for Component {
             }
         };
 
+        // Tool calls from chat-state when we are proxying its MCP servers
+        if let Some(mcp_proxy) = task_state.mcp_proxy.as_mut()
+            && let Ok(mcp_request) = from_slice::<McpActorRequest>(&data)
+        {
+            // The turn isn't synced until it ends, so viewers see calls here
+            if let McpActorRequest::ToolsCall { name, args } = &mcp_request
+                && let Some(server) = &task_state.http_server
+            {
+                server.broadcast(
+                    &TaskEvent::ToolCall {
+                        id: request_id.clone(),
+                        name: name.clone(),
+                        input: args.clone(),
+                    },
+                    &task_state.metadata,
+                );
+            }
+            let response = mcp_proxy.handle(&request_id, mcp_request, &mut task_state.audit_log);
+
+            // A held call is answered once it is approved or rejected
+            let response_data = match response {
+                Some(response) => Some(
+                    to_vec(&response)
+                        .map_err(|e| format!("Failed to serialize response: {}", e))?,
+                ),
+                None => {
+                    if let Some(call) = mcp_proxy.pending_calls().last().cloned() {
+                        task_state.emit(&TaskEvent::ToolCallPending {
+                            request_id: call.request_id,
+                            name: call.tool,
+                            input: call.args,
+                        });
+                    }
+                    None
+                }
+            };
+
+            let updated_state_bytes = to_vec(&task_state)
+                .map_err(|e| format!("Failed to serialize current state: {}", e))?;
+            return Ok((Some(updated_state_bytes), (response_data,)));
+        }
+
         // Parse the request
         let request: TaskManagerRequest = match from_slice(&data) {
             Ok(req) => {
-                log(&format!("Parsed request: {:?}", req));
+                log_request("Parsed request", &req);
                 req
             }
             Err(e) => {
                 let error_msg = format!("Failed to parse request: {}", e);
-                log(&error_msg);
+                error(&error_msg);
                 let error_response = TaskManagerResponse::Error { message: error_msg };
                 return Ok((
                     Some(to_vec(&task_state).unwrap_or_default()),
@@ -350,100 +1054,7 @@ impl MessageServerClient for Component {
         };
 
         // Handle the request
-        let response = match request {
-            TaskManagerRequest::StartChat => {
-                log("Handling StartChat request");
-
-                // Send initial message if configured
-                if let Some(initial_msg) = &task_state.initial_message {
-                    log("Sending initial message to chat state actor");
-                    let message = Message {
-                        role: Role::User,
-                        content: vec![MessageContent::Text {
-                            text: initial_msg.clone(),
-                        }],
-                    };
-
-                    match task_state.get_chat_state_actor_id() {
-                        Ok(chat_actor_id) => {
-                            let add_message_request =
-                                protocol::ChatStateRequest::AddMessage { message };
-                            match to_vec(&add_message_request) {
-                                Ok(request_data) => match send(chat_actor_id, &request_data) {
-                                    Ok(_) => {
-                                        log("Initial message sent successfully");
-                                    }
-                                    Err(e) => {
-                                        log(&format!("Failed to send initial message: {:?}", e));
-                                    }
-                                },
-                                Err(e) => {
-                                    log(&format!("Failed to serialize initial message: {}", e));
-                                }
-                            }
-
-                            let generate_response_request =
-                                protocol::ChatStateRequest::GenerateCompletion;
-                            match to_vec(&generate_response_request) {
-                                Ok(request_data) => match send(chat_actor_id, &request_data) {
-                                    Ok(_) => {
-                                        log("Generate completion request sent to chat state actor");
-                                    }
-                                    Err(e) => {
-                                        log(&format!("Failed to send generate request: {:?}", e));
-                                    }
-                                },
-                                Err(e) => {
-                                    log(&format!("Failed to serialize generate request: {}", e));
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            log(&format!("Chat state actor not available: {}", e));
-                        }
-                    }
-                }
-
-                TaskManagerResponse::Success
-            }
-            TaskManagerRequest::GetChatStateActorId => match task_state.get_chat_state_actor_id() {
-                Ok(chat_actor_id) => {
-                    log(&format!("Returning chat state actor ID: {}", chat_actor_id));
-                    TaskManagerResponse::ChatStateActorId {
-                        actor_id: chat_actor_id.clone(),
-                    }
-                }
-                Err(e) => TaskManagerResponse::Error { message: e },
-            },
-            TaskManagerRequest::AddMessage { message } => {
-                match task_state.get_chat_state_actor_id() {
-                    Ok(chat_actor_id) => {
-                        let add_message_request =
-                            protocol::ChatStateRequest::AddMessage { message };
-
-                        match to_vec(&add_message_request) {
-                            Ok(request_data) => match send(chat_actor_id, &request_data) {
-                                Ok(_) => {
-                                    log("Message forwarded to chat state actor");
-                                    TaskManagerResponse::Success
-                                }
-                                Err(e) => {
-                                    let error_msg = format!("Failed to forward message: {:?}", e);
-                                    log(&error_msg);
-                                    TaskManagerResponse::Error { message: error_msg }
-                                }
-                            },
-                            Err(e) => {
-                                let error_msg = format!("Failed to serialize message: {}", e);
-                                log(&error_msg);
-                                TaskManagerResponse::Error { message: error_msg }
-                            }
-                        }
-                    }
-                    Err(e) => TaskManagerResponse::Error { message: e },
-                }
-            }
-        };
+        let response = handle_task_request(&mut task_state, request);
 
         // Serialize response and return
         let response_data =
@@ -459,7 +1070,7 @@ impl MessageServerClient for Component {
         params: (String, Vec<u8>),
     ) -> Result<(Option<Vec<u8>>, (ChannelAccept,)), String> {
         let (_channel_id, _data) = params;
-        log("Task manager: Channel open request");
+        info("Task manager: Channel open request");
         Ok((
             state,
             (ChannelAccept {
@@ -474,7 +1085,7 @@ impl MessageServerClient for Component {
         params: (ChannelId,),
     ) -> Result<(Option<Vec<u8>>,), String> {
         let (channel_id,) = params;
-        log(&format!("Task manager: Channel closed: {}", channel_id));
+        info(&format!("Task manager: Channel closed: {}", channel_id));
         Ok((state,))
     }
 
@@ -483,7 +1094,7 @@ impl MessageServerClient for Component {
         params: (ChannelId, Vec<u8>),
     ) -> Result<(Option<Vec<u8>>,), String> {
         let (channel_id, _data) = params;
-        log(&format!(
+        debug(&format!(
             "Task manager: Received channel message on: {}",
             channel_id
         ));
@@ -491,59 +1102,216 @@ impl MessageServerClient for Component {
     }
 }
 
-// Helper functions
-fn create_task_config(self_id: &str, config: &TaskManagerConfig) -> Value {
-    log("Creating task configuration...");
+impl HttpHandlers for Component {
+    fn handle_request(
+        state: Option<Vec<u8>>,
+        params: (HandlerId, HttpRequest),
+    ) -> Result<(Option<Vec<u8>>, (HttpResponse,)), String> {
+        let (_handler_id, http_request) = params;
+        debug(&format!(
+            "Task manager handling HTTP request: {} {}",
+            http_request.method, http_request.uri
+        ));
+
+        let mut task_state: TaskManagerState = match state {
+            Some(state_bytes) => match TaskManagerState::from_bytes(&state_bytes) {
+                Ok(state) => state,
+                Err(e) => {
+                    let error_msg = format!("Failed to deserialize task state: {}", e);
+                    error(&error_msg);
+                    return Ok((None, (http::error_response(500, error_msg),)));
+                }
+            },
+            None => {
+                let error_msg = "No state available".to_string();
+                return Ok((None, (http::error_response(500, error_msg),)));
+            }
+        };
 
-    // Default system prompt if none provided
-    let default_system_prompt = "You are an AI assistant that helps users complete tasks efficiently. You have access to various tools and can help with a wide range of activities.";
+        let response = match http::parse_request(&http_request) {
+            Ok(request) => {
+                log_request("Parsed HTTP request", &request);
+                let error_status = http::error_status(&request);
+                http::into_response(&handle_task_request(&mut task_state, request), error_status)
+            }
+            Err(response) => response,
+        };
 
-    let system_prompt = config
-        .system_prompt
-        .as_deref()
-        .unwrap_or(default_system_prompt);
+        let updated_state_bytes =
+            to_vec(&task_state).map_err(|e| format!("Failed to serialize current state: {}", e))?;
+
+        Ok((Some(updated_state_bytes), (response,)))
+    }
+
+    fn handle_middleware(
+        state: Option<Vec<u8>>,
+        params: (HandlerId, HttpRequest),
+    ) -> Result<(Option<Vec<u8>>, (MiddlewareResult,)), String> {
+        let (_handler_id, http_request) = params;
+        Ok((
+            state,
+            (MiddlewareResult {
+                proceed: true,
+                request: http_request,
+            },),
+        ))
+    }
+
+    fn handle_websocket_connect(
+        state: Option<Vec<u8>>,
+        params: (HandlerId, u64, String, Option<String>),
+    ) -> Result<(Option<Vec<u8>>,), String> {
+        let (_handler_id, connection_id, path, _protocol) = params;
+        info(&format!(
+            "Task manager: WebSocket connection {} opened on {}",
+            connection_id, path
+        ));
+
+        let mut task_state: TaskManagerState = match state {
+            Some(state_bytes) => TaskManagerState::from_bytes(&state_bytes)
+                .map_err(|e| format!("Failed to deserialize task state: {}", e))?,
+            None => return Ok((None,)),
+        };
+
+        let status = task_state.status;
+        if let Some(server) = task_state.http_server.as_mut() {
+            server.connections.push(connection_id);
+            // Let the new viewer know where the task currently stands
+            server.send_event(
+                connection_id,
+                &TaskEvent::StatusChanged { status },
+                &task_state.metadata,
+            );
+        }
+
+        let updated_state_bytes =
+            to_vec(&task_state).map_err(|e| format!("Failed to serialize current state: {}", e))?;
+        Ok((Some(updated_state_bytes),))
+    }
+
+    fn handle_websocket_message(
+        state: Option<Vec<u8>>,
+        params: (HandlerId, u64, WebsocketMessage),
+    ) -> Result<(Option<Vec<u8>>, (Vec<WebsocketMessage>,)), String> {
+        let (_handler_id, connection_id, ws_message) = params;
+        debug(&format!(
+            "Task manager: WebSocket message on connection {}",
+            connection_id
+        ));
+
+        let mut task_state: TaskManagerState = match state {
+            Some(state_bytes) => TaskManagerState::from_bytes(&state_bytes)
+                .map_err(|e| format!("Failed to deserialize task state: {}", e))?,
+            None => return Ok((None, (vec![],))),
+        };
+
+        // Anything other than a text or binary payload (pings, close frames) needs no reply
+        let Some(message) = http::parse_client_message(&ws_message) else {
+            return Ok((Some(to_vec(&task_state).unwrap_or_default()), (vec![],)));
+        };
+
+        let response =
+            handle_task_request(&mut task_state, TaskManagerRequest::AddMessage { message });
+
+        let updated_state_bytes =
+            to_vec(&task_state).map_err(|e| format!("Failed to serialize current state: {}", e))?;
+        Ok((
+            Some(updated_state_bytes),
+            (vec![http::response_message(&response)],),
+        ))
+    }
+
+    fn handle_websocket_disconnect(
+        state: Option<Vec<u8>>,
+        params: (HandlerId, u64),
+    ) -> Result<(Option<Vec<u8>>,), String> {
+        let (_handler_id, connection_id) = params;
+        info(&format!(
+            "Task manager: WebSocket connection {} closed",
+            connection_id
+        ));
+
+        let mut task_state: TaskManagerState = match state {
+            Some(state_bytes) => TaskManagerState::from_bytes(&state_bytes)
+                .map_err(|e| format!("Failed to deserialize task state: {}", e))?,
+            None => return Ok((None,)),
+        };
+
+        if let Some(server) = task_state.http_server.as_mut() {
+            server.connections.retain(|id| *id != connection_id);
+        }
+
+        let updated_state_bytes =
+            to_vec(&task_state).map_err(|e| format!("Failed to serialize current state: {}", e))?;
+        Ok((Some(updated_state_bytes),))
+    }
+}
+
+// Helper functions
+fn create_task_config(
+    self_id: &str,
+    config: &TaskManagerConfig,
+    metadata: &TaskMetadata,
+    presets: &ModelPresets,
+    mcp_pool: &[mcp::SharedMcpServer],
+    proxied: bool,
+) -> Result<Value, String> {
+    debug("Creating task configuration...");
+
+    // Compose the system prompt, falling back to a default if none provided
+    let system_prompt = prompt::compose_system_prompt(config.system_prompt.as_ref())?;
 
     // Add task completion instruction to system prompt
     let completion_instruction = "\n\nIMPORTANT: When you have completed your assigned task, you MUST call the 'task_complete' tool to signal that the work is finished. This allows the system to properly conclude the task session.";
 
     let final_system_prompt = format!("{}{}", system_prompt, completion_instruction);
 
-    // Default model config
-    let default_model_proxy = serde_json::json!({
-        "manifest_path": "https://github.com/colinrozzi/google-proxy/releases/latest/download/manifest.toml",
-        "model": "gemini-2.0-flash",
-    });
+    // Expand the named preset, if any; explicit fields take precedence
+    let preset = selected_preset(config, presets)?;
 
-    let model_proxy = config.model_proxy.as_ref().unwrap_or(&default_model_proxy);
+    // Start with the first model in the fallback chain
+    let model_chain = resolve_model_chain(config, presets)?;
+    let model_proxy = &model_chain[0];
 
     // Default temperature and tokens
-    let temperature = config.temperature.unwrap_or(0.7);
-    let max_tokens = config.max_tokens.unwrap_or(8192);
-    let title = config
-        .other
-        .get("title")
-        .and_then(Value::as_str)
-        .unwrap_or("Task");
+    let temperature = config
+        .temperature
+        .or(preset.and_then(|p| p.temperature))
+        .unwrap_or(0.7);
+    let max_tokens = config
+        .max_tokens
+        .or(preset.and_then(|p| p.max_tokens))
+        .unwrap_or(8192);
+    let title = metadata.title.as_deref().unwrap_or("Task");
+
+    // The task monitor requires a result matching the output schema, if any
+    let mut task_monitor_state = serde_json::json!({
+        "management_actor": self_id,
+    });
+    if let Some(output_schema) = &config.output_schema {
+        task_monitor_state["result_schema"] = output_schema.clone();
+    }
 
     let task_mcp_server = McpServer {
+        name: None,
         actor_id: None,
         config: McpConfig::Actor(ActorMcpConfig {
             manifest_path: TASK_MONITOR_MANIFEST_PATH.to_string(),
-            init_state: Some(serde_json::json!({
-                "management_actor": self_id,
-            })),
+            init_state: metadata.add_to_init_state(Some(task_monitor_state)),
         }),
         tools: None,
     };
 
     let mut mcp_servers: Vec<McpServer> = config.mcp_servers.clone().unwrap_or(vec![]).to_vec();
+    if proxied {
+        // Actor servers and the pool are reached through us; stdio servers can't be
+        mcp_servers.retain(|server| matches!(server.config, McpConfig::StdPipe(_)));
+        mcp_servers.push(proxy::McpProxy::chat_state_server(self_id));
+    } else {
+        mcp_servers.extend(mcp_pool.iter().map(mcp::SharedMcpServer::to_mcp_server));
+    }
     mcp_servers.append(&mut vec![task_mcp_server]);
 
-    log(&format!("Using MCP servers: {:?}", mcp_servers));
-    log(&format!("Using model: {:?}", model_proxy));
-    log(&format!("Using temperature: {}", temperature));
-    log(&format!("Using max_tokens: {}", max_tokens));
-
     // Build the final configuration
     let mut final_config = serde_json::json!({
             "config": {
@@ -568,18 +1336,1300 @@ fn create_task_config(self_id: &str, config: &TaskManagerConfig) -> Value {
         }
     }
 
-    log(&format!("Created final task config: {}", final_config));
-    final_config
+    Ok(final_config)
+}
+
+fn selected_preset<'a>(
+    config: &TaskManagerConfig,
+    presets: &'a ModelPresets,
+) -> Result<Option<&'a models::ModelPreset>, String> {
+    config
+        .model
+        .as_deref()
+        .map(|name| models::get_preset(presets, name))
+        .transpose()
+}
+
+fn resolve_model_chain(
+    config: &TaskManagerConfig,
+    presets: &ModelPresets,
+) -> Result<Vec<Value>, String> {
+    let preset = selected_preset(config, presets)?;
+    models::model_chain(config.model_proxy.as_ref(), preset, presets)
+}
+
+fn handle_task_request(
+    task_state: &mut TaskManagerState,
+    request: TaskManagerRequest,
+) -> TaskManagerResponse {
+    match request {
+        TaskManagerRequest::StartChat => {
+            info("Handling StartChat request");
+
+            // Our children do the work; our chat-state gets the transcript
+            // of the one whose result we keep
+            if task_state.race.is_some() || task_state.samples.is_some() {
+                return match start_children(task_state) {
+                    Ok(()) => TaskManagerResponse::Success,
+                    Err(e) => {
+                        error(&format!("Failed to start child tasks: {}", e));
+                        task_state.set_status(TaskStatus::Failed);
+                        TaskManagerResponse::Error { message: e }
+                    }
+                };
+            }
+
+            // A fork starts from its parent's transcript, then the initial message
+            let mut messages = std::mem::take(&mut task_state.history);
+            if let Some(initial_msg) = &task_state.initial_message {
+                messages.push(Message {
+                    role: Role::User,
+                    content: vec![MessageContent::Text {
+                        text: initial_msg.clone(),
+                    }],
+                });
+            }
+            // Only a user message at the end leaves something to answer
+            let awaiting_reply = messages
+                .last()
+                .is_some_and(|message| matches!(message.role, Role::User));
+
+            if !messages.is_empty() {
+                match task_state.get_chat_state_actor_id() {
+                    Ok(chat_actor_id) => {
+                        debug(&format!(
+                            "Sending {} starting messages to chat state actor",
+                            messages.len()
+                        ));
+                        for message in messages {
+                            let add_message_request = ChatStateRequest::AddMessage { message };
+                            match to_vec(&add_message_request) {
+                                Ok(request_data) => {
+                                    if let Err(e) = send(chat_actor_id, &request_data) {
+                                        warn(&format!("Failed to send starting message: {:?}", e));
+                                    }
+                                }
+                                Err(e) => {
+                                    warn(&format!("Failed to serialize starting message: {}", e));
+                                }
+                            }
+                        }
+
+                        if awaiting_reply && let Err(e) = schedule_generation(task_state) {
+                            warn(&format!("Failed to schedule completion: {}", e));
+                        }
+                    }
+                    Err(e) => {
+                        warn(&format!("Chat state actor not available: {}", e));
+                    }
+                }
+            }
+
+            // An interactive task with nothing to answer waits for the next message
+            if !awaiting_reply && task_state.mode == TaskMode::Interactive {
+                task_state.set_status(TaskStatus::WaitingForInput);
+            } else {
+                task_state.set_status(TaskStatus::Running);
+            }
+            TaskManagerResponse::Success
+        }
+        TaskManagerRequest::GetChatStateActorId => match task_state.get_chat_state_actor_id() {
+            Ok(chat_actor_id) => {
+                debug(&format!("Returning chat state actor ID: {}", chat_actor_id));
+                TaskManagerResponse::ChatStateActorId {
+                    actor_id: chat_actor_id.clone(),
+                }
+            }
+            Err(e) => TaskManagerResponse::Error { message: e },
+        },
+        TaskManagerRequest::AddMessage { message } => {
+            let response = forward_message(task_state, message);
+            if !matches!(response, TaskManagerResponse::Success) {
+                return response;
+            }
+
+            // A paused task picks up from the new message, and an interactive
+            // one answers every message
+            let resume = match task_state.status {
+                TaskStatus::WaitingForInput | TaskStatus::Stalled => true,
+                TaskStatus::Running => task_state.mode == TaskMode::Interactive,
+                _ => false,
+            };
+            if resume {
+                task_state.idle_nudges = 0;
+                task_state.set_status(TaskStatus::Running);
+                if let Err(e) = schedule_generation(task_state) {
+                    warn(&format!("Failed to schedule completion: {}", e));
+                }
+            }
+            response
+        }
+        TaskManagerRequest::GetStatus => TaskManagerResponse::Status(Box::new(StatusReport {
+            status: task_state.status,
+            mode: task_state.mode,
+            chat_state_actor_id: task_state.chat_state_actor_id.clone(),
+            model: task_state.current_model(),
+            result: task_state.result.clone(),
+            metadata: task_state.metadata.clone(),
+            completions: task_state.completions.clone(),
+            forks: task_state.forks.clone(),
+            race: task_state.race.clone(),
+            samples: task_state.samples.clone(),
+            tool_usage: task_state.mcp_proxy.as_ref().map(proxy::McpProxy::usage),
+            tool_cache: task_state
+                .mcp_proxy
+                .as_ref()
+                .and_then(proxy::McpProxy::cache_stats),
+        })),
+        TaskManagerRequest::GetTranscript => {
+            // Turns generated without waiting haven't been attributed yet;
+            // the model can only have changed on a turn we waited for
+            let model = task_state.current_model();
+            record_model_turns(task_state, &model);
+            match fetch_transcript(task_state) {
+                Ok(messages) => TaskManagerResponse::Transcript {
+                    messages,
+                    model_turns: task_state.model_turns.clone(),
+                },
+                Err(e) => {
+                    warn(&format!("Failed to fetch transcript: {}", e));
+                    TaskManagerResponse::Error { message: e }
+                }
+            }
+        }
+        TaskManagerRequest::GetSystemPrompt => {
+            match task_state.original_config["config"]["system_prompt"].as_str() {
+                Some(system_prompt) => TaskManagerResponse::SystemPrompt {
+                    system_prompt: system_prompt.to_string(),
+                },
+                None => TaskManagerResponse::Error {
+                    message: "System prompt not found in task config".to_string(),
+                },
+            }
+        }
+        TaskManagerRequest::GetMcpPool => TaskManagerResponse::McpPool {
+            servers: task_state
+                .mcp_pool
+                .iter()
+                .cloned()
+                .map(|mut server| {
+                    server.init_state = server.init_state.as_ref().map(secrets::mask);
+                    server
+                })
+                .collect(),
+        },
+        TaskManagerRequest::GetAuditLog => TaskManagerResponse::AuditLog {
+            entries: task_state.audit_log.clone(),
+        },
+        TaskManagerRequest::AddMcpServer { server } => match add_mcp_server(task_state, server) {
+            Ok(()) => TaskManagerResponse::Success,
+            Err(e) => {
+                warn(&format!("Failed to add MCP server: {}", e));
+                TaskManagerResponse::Error { message: e }
+            }
+        },
+        TaskManagerRequest::RemoveMcpServer { name } => {
+            match remove_mcp_server(task_state, &name) {
+                Ok(()) => TaskManagerResponse::Success,
+                Err(e) => {
+                    warn(&format!("Failed to remove MCP server {}: {}", name, e));
+                    TaskManagerResponse::Error { message: e }
+                }
+            }
+        }
+        TaskManagerRequest::ExportAudit { store_id } => {
+            info("Exporting audit document");
+            let document = audit::export(&task_state.actor_id, &audit_children(task_state));
+            match store_id {
+                Some(store_id) => match audit::store_document(&store_id, &document) {
+                    Ok(hash) => TaskManagerResponse::AuditStored { store_id, hash },
+                    Err(e) => {
+                        warn(&e);
+                        TaskManagerResponse::Error { message: e }
+                    }
+                },
+                None => TaskManagerResponse::AuditDocument { document },
+            }
+        }
+        TaskManagerRequest::Reopen { message } => match reopen(task_state, message) {
+            Ok(()) => TaskManagerResponse::Success,
+            Err(e) => {
+                warn(&format!("Failed to reopen task: {}", e));
+                TaskManagerResponse::Error { message: e }
+            }
+        },
+        TaskManagerRequest::Fork {
+            at_message_index,
+            overrides,
+            config_only,
+        } => match fork_task(
+            task_state,
+            at_message_index,
+            overrides.as_ref(),
+            config_only,
+        ) {
+            Ok(response) => response,
+            Err(e) => {
+                warn(&format!("Failed to fork task: {}", e));
+                TaskManagerResponse::Error { message: e }
+            }
+        },
+        TaskManagerRequest::GetPendingToolCalls => TaskManagerResponse::PendingToolCalls {
+            calls: task_state
+                .mcp_proxy
+                .as_ref()
+                .map(|mcp_proxy| mcp_proxy.pending_calls().to_vec())
+                .unwrap_or_default(),
+        },
+        TaskManagerRequest::ApproveToolCall { request_id } => {
+            match decide_tool_call(task_state, &request_id, true, None) {
+                Ok(()) => TaskManagerResponse::Success,
+                Err(e) => {
+                    warn(&format!(
+                        "Failed to approve tool call {}: {}",
+                        request_id, e
+                    ));
+                    TaskManagerResponse::Error { message: e }
+                }
+            }
+        }
+        TaskManagerRequest::RejectToolCall { request_id, reason } => {
+            match decide_tool_call(task_state, &request_id, false, reason) {
+                Ok(()) => TaskManagerResponse::Success,
+                Err(e) => {
+                    warn(&format!("Failed to reject tool call {}: {}", request_id, e));
+                    TaskManagerResponse::Error { message: e }
+                }
+            }
+        }
+        TaskManagerRequest::Cancel => {
+            info("Handling Cancel request");
+            task_state.set_status(TaskStatus::Cancelled);
+
+            if let Ok(chat_actor_id) = task_state.get_chat_state_actor_id()
+                && let Err(e) = stop_child(chat_actor_id)
+            {
+                warn(&format!("Failed to stop chat state actor: {}", e));
+            }
+
+            notify_finished(task_state);
+            info("Task cancelled, shutting down task manager");
+            let _ = shutdown(None);
+            TaskManagerResponse::Success
+        }
+    }
+}
+
+/// The children whose chains belong in the audit, as `(role, actor_id)`
+fn audit_children(task_state: &TaskManagerState) -> Vec<(String, String)> {
+    let mut children = vec![];
+    if let Some(chat_actor_id) = &task_state.chat_state_actor_id {
+        children.push(("chat_state".to_string(), chat_actor_id.clone()));
+    }
+
+    let proxied = task_state
+        .mcp_proxy
+        .iter()
+        .flat_map(|mcp_proxy| mcp_proxy.servers.iter());
+    let mcp_actor_ids = task_state
+        .mcp_pool
+        .iter()
+        .chain(proxied)
+        .filter_map(|server| server.actor_id.clone())
+        .chain(task_state.owned_mcp_actors.values().cloned());
+    for actor_id in mcp_actor_ids {
+        if !children.iter().any(|(_, id)| id == &actor_id) {
+            children.push(("mcp_server".to_string(), actor_id));
+        }
+    }
+
+    children.extend(task_state.retired_children.iter().cloned());
+    children
+}
+
+fn request_chat_state(
+    chat_actor_id: &str,
+    chat_request: &ChatStateRequest,
+) -> Result<ChatStateResponse, String> {
+    let request_data = to_vec(chat_request)
+        .map_err(|e| format!("Failed to serialize chat state request: {}", e))?;
+    let response_data = request(chat_actor_id, &request_data)
+        .map_err(|e| format!("Chat state request failed: {}", e))?;
+
+    from_slice::<ChatStateResponse>(&response_data)
+        .map_err(|e| format!("Failed to parse chat state response: {}", e))
+}
+
+fn fetch_transcript(task_state: &TaskManagerState) -> Result<Vec<Message>, String> {
+    // Chat-state is blocked on a held tool call until it is decided, so a
+    // request to it now would never be answered
+    if task_state
+        .mcp_proxy
+        .as_ref()
+        .is_some_and(|mcp_proxy| !mcp_proxy.pending_calls().is_empty())
+    {
+        return Err(
+            "Chat state is waiting on a held tool call, approve or reject it first".to_string(),
+        );
+    }
+    // Likewise, the turn may call a tool through us while we wait on chat-state
+    if task_state.proxied_turn {
+        return Err(
+            "Chat state is generating and may call back into the task manager, try again once the task completes"
+                .to_string(),
+        );
+    }
+    let chat_actor_id = task_state.get_chat_state_actor_id()?;
+
+    match request_chat_state(chat_actor_id, &ChatStateRequest::GetHistory)? {
+        ChatStateResponse::History { messages } => Ok(messages),
+        ChatStateResponse::Error { error } => Err(format!(
+            "Chat state actor returned error {}: {}",
+            error.code, error.message
+        )),
+        other => Err(format!("Unexpected chat state response: {:?}", other)),
+    }
+}
+
+/// Logs a request as an event rather than a formatted line, so secrets in
+/// it, such as an MCP server's init state, are redacted
+fn log_request(message: &str, request: &TaskManagerRequest) {
+    let fields = serde_json::to_value(request).unwrap_or_default();
+    let kind = fields["type"].as_str().unwrap_or_default().to_string();
+    logging::event(
+        LogLevel::Debug,
+        "request_received",
+        &format!("{}: {}", message, kind),
+        fields,
+    );
+}
+
+/// Emits events for any transcript messages we haven't seen yet and returns them
+fn sync_transcript(task_state: &mut TaskManagerState) -> Vec<(usize, Message)> {
+    let messages = match fetch_transcript(task_state) {
+        Ok(messages) => messages,
+        Err(e) => {
+            warn(&format!("Failed to sync transcript: {}", e));
+            return vec![];
+        }
+    };
+
+    let new_messages: Vec<(usize, Message)> = messages
+        .into_iter()
+        .enumerate()
+        .skip(task_state.seen_messages)
+        .collect();
+
+    for (index, message) in &new_messages {
+        for event in events::message_events(*index, message) {
+            // Proxied calls were sent to viewers as they were made
+            if task_state.mcp_proxy.is_some() && matches!(event, TaskEvent::ToolCall { .. }) {
+                continue;
+            }
+            task_state.emit(&event);
+        }
+        task_state.seen_messages = index + 1;
+    }
+
+    new_messages
+}
+
+/// Queues work for ourselves, to run once the current handler returns
+fn send_to_self(task_state: &TaskManagerState, message: &SelfMessage) -> Result<(), String> {
+    let message_data =
+        to_vec(message).map_err(|e| format!("Failed to serialize {:?}: {}", message, e))?;
+    send(&task_state.actor_id, &message_data)
+        .map_err(|e| format!("Failed to send {:?}: {:?}", message, e))
+}
+
+/// Asks ourselves to run the next completion once the current handler returns
+fn schedule_generation(task_state: &TaskManagerState) -> Result<(), String> {
+    send_to_self(task_state, &SelfMessage::GenerateCompletion)?;
+    debug("Completion generation scheduled");
+    Ok(())
+}
+
+fn generate_completion(task_state: &mut TaskManagerState) -> Result<(), String> {
+    if task_state.replay.is_some() {
+        return replay_completion(task_state);
+    }
+
+    // Waiting on chat-state holds our mailbox for the whole turn, so
+    // GetStatus and Cancel queue behind it. We only wait when something
+    // needs the end of the turn: a fallback model, idle detection, an
+    // interactive pause or live viewers waiting for the turn's messages.
+    // When proxying we can't wait at all, as chat-state calls back into us
+    // for tools while generating.
+    let needs_turn_end = task_state.model_chain.len() > 1
+        || task_state.idle.is_some()
+        || task_state.mode == TaskMode::Interactive
+        || task_state
+            .http_server
+            .as_ref()
+            .is_some_and(http::HttpServerState::live_view);
+    if task_state.mcp_proxy.is_some() || !needs_turn_end {
+        let chat_actor_id = task_state.get_chat_state_actor_id()?;
+        let request_data = to_vec(&ChatStateRequest::GenerateCompletion)
+            .map_err(|e| format!("Failed to serialize generate request: {}", e))?;
+        send(chat_actor_id, &request_data)
+            .map_err(|e| format!("Failed to send generate request: {:?}", e))?;
+        debug("Completion requested without waiting");
+        task_state.proxied_turn = task_state.mcp_proxy.is_some();
+        return Ok(());
+    }
+
+    loop {
+        let chat_actor_id = task_state.get_chat_state_actor_id()?.clone();
+
+        debug(&format!(
+            "Requesting completion from chat state actor using {}",
+            task_state.current_model()
+        ));
+        match request_chat_state(&chat_actor_id, &ChatStateRequest::GenerateCompletion)? {
+            ChatStateResponse::Error { error } => {
+                warn(&format!(
+                    "Model {} failed with {}: {}",
+                    task_state.current_model(),
+                    error.code,
+                    error.message
+                ));
+                if let Err(e) = fall_back_to_next_model(task_state) {
+                    task_state.set_status(TaskStatus::Failed);
+                    finish_task(task_state);
+                    return Err(format!("No fallback model available: {}", e));
+                }
+            }
+            _ => break,
+        }
+    }
+    debug("Completion generated");
+
+    let model = task_state.current_model();
+    record_model_turns(task_state, &model);
+
+    // A TaskComplete sent during generation is still in our mailbox, so
+    // the check is queued behind it rather than run now
+    if task_state.idle.is_some() || task_state.mode == TaskMode::Interactive {
+        send_to_self(task_state, &SelfMessage::CheckIdle)?;
+    }
+    Ok(())
+}
+
+/// Runs when the model stopped without completing the task. Interactive
+/// tasks wait for the next message; others nudge the model, and are marked
+/// stalled once the nudges run out.
+fn check_idle(task_state: &mut TaskManagerState) -> Result<(), String> {
+    if task_state.status != TaskStatus::Running || !idle::is_idle(&fetch_transcript(task_state)?) {
+        return Ok(());
+    }
+    if task_state.mode == TaskMode::Interactive {
+        task_state.set_status(TaskStatus::WaitingForInput);
+        return Ok(());
+    }
+    let Some(idle_config) = task_state.idle.clone() else {
+        return Ok(());
+    };
+
+    let max_nudges = idle_config.max_nudges();
+    if task_state.idle_nudges < max_nudges {
+        task_state.idle_nudges += 1;
+        info(&format!(
+            "Model stopped without completing the task, sending nudge {} of {}",
+            task_state.idle_nudges, max_nudges
+        ));
+        send_feedback(task_state, idle_config.nudge_message());
+        return Ok(());
+    }
+
+    warn(&format!(
+        "Model stopped without completing the task after {} nudges, marking it stalled",
+        task_state.idle_nudges
+    ));
+    task_state.set_status(TaskStatus::Stalled);
+    notify_stalled(task_state);
+    Ok(())
+}
+
+/// Records which model produced the assistant messages added since the last sync
+fn record_model_turns(task_state: &mut TaskManagerState, model: &str) {
+    for (index, message) in sync_transcript(task_state) {
+        if matches!(message.role, Role::Assistant) {
+            task_state.model_turns.push(ModelTurn {
+                index,
+                model: model.to_string(),
+            });
+        }
+    }
+}
+
+/// Feeds the next recorded input to the task, then schedules the one after.
+/// Generations scheduled by the input are queued first, so they run before it.
+fn replay_next(task_state: &mut TaskManagerState) -> Result<(), String> {
+    let Some(input) = task_state
+        .replay
+        .as_mut()
+        .and_then(replay::ReplayState::next_input)
+    else {
+        info("Replay finished");
+        return Ok(());
+    };
+
+    match input {
+        replay::ReplayInput::Request(request) => {
+            log_request("Replaying request", &request);
+            let response = handle_task_request(task_state, request);
+            logging::event(
+                LogLevel::Debug,
+                "request_replayed",
+                "Replayed request returned",
+                serde_json::to_value(&response).unwrap_or_default(),
+            );
+        }
+        replay::ReplayInput::TaskComplete(completion) => {
+            debug("Replaying TaskComplete message");
+            handle_task_complete(task_state, completion);
+        }
+    }
+
+    send_to_self(task_state, &SelfMessage::ReplayNext)
+}
+
+/// Plays back the recorded model turn, tool calls and results included,
+/// instead of asking the model
+fn replay_completion(task_state: &mut TaskManagerState) -> Result<(), String> {
+    let current_len = fetch_transcript(task_state)?.len();
+    let turn = match &task_state.replay {
+        Some(replay) => replay.next_turn(current_len).to_vec(),
+        None => vec![],
+    };
+    debug(&format!("Replaying {} recorded messages", turn.len()));
+
+    let chat_actor_id = task_state.get_chat_state_actor_id()?;
+    for message in turn {
+        let request_data = to_vec(&ChatStateRequest::AddMessage { message })
+            .map_err(|e| format!("Failed to serialize message: {}", e))?;
+        send(chat_actor_id, &request_data)
+            .map_err(|e| format!("Failed to replay message: {:?}", e))?;
+    }
+
+    record_model_turns(task_state, "replay");
+    Ok(())
+}
+
+/// Replaces the chat-state actor with one using the next model in the chain,
+/// carrying the transcript over so the conversation can continue
+fn fall_back_to_next_model(task_state: &mut TaskManagerState) -> Result<(), String> {
+    let next_index = task_state.model_index + 1;
+    let model_proxy = task_state
+        .model_chain
+        .get(next_index)
+        .cloned()
+        .ok_or_else(|| "Model fallback chain exhausted".to_string())?;
+
+    info(&format!(
+        "Falling back to model {}",
+        models::model_label(&model_proxy)
+    ));
+
+    let mut chat_config = task_state.original_config.clone();
+    chat_config["config"]["model_proxy"] = model_proxy;
+    respawn_chat_state(task_state, &chat_config)?;
+    task_state.model_index = next_index;
+    Ok(())
+}
+
+/// The config chat-state is spawned with, using the model currently in use
+fn chat_state_config(task_state: &TaskManagerState) -> Value {
+    let mut chat_config = task_state.original_config.clone();
+    if let Some(model_proxy) = task_state.model_chain.get(task_state.model_index) {
+        chat_config["config"]["model_proxy"] = model_proxy.clone();
+    }
+    chat_config
+}
+
+/// Replaces the chat-state actor with one spawned from `chat_config`,
+/// carrying the transcript over so the conversation can continue
+fn respawn_chat_state(
+    task_state: &mut TaskManagerState,
+    chat_config: &Value,
+) -> Result<(), String> {
+    let transcript = fetch_transcript(task_state)?;
+    let old_chat_actor_id = task_state.get_chat_state_actor_id()?.clone();
+    let chat_actor_id = spawn_chat_state_actor(chat_config)?;
+
+    for message in transcript {
+        let request_data = to_vec(&ChatStateRequest::AddMessage { message })
+            .map_err(|e| format!("Failed to serialize message: {}", e))?;
+        send(&chat_actor_id, &request_data)
+            .map_err(|e| format!("Failed to carry over message: {:?}", e))?;
+    }
+
+    task_state.set_chat_state_actor_id(chat_actor_id);
+
+    if let Err(e) = stop_child(&old_chat_actor_id) {
+        warn(&format!("Failed to stop previous chat state actor: {}", e));
+    }
+    task_state
+        .retired_children
+        .push(("chat_state".to_string(), old_chat_actor_id));
+    Ok(())
+}
+
+fn chat_mcp_servers(task_state: &TaskManagerState) -> Result<Vec<McpServer>, String> {
+    serde_json::from_value(task_state.original_config["config"]["mcp_servers"].clone())
+        .map_err(|e| format!("Failed to read MCP servers from task config: {}", e))
+}
+
+fn set_chat_mcp_servers(
+    task_state: &mut TaskManagerState,
+    servers: &[McpServer],
+) -> Result<(), String> {
+    task_state.original_config["config"]["mcp_servers"] = serde_json::to_value(servers)
+        .map_err(|e| format!("Failed to serialize MCP servers: {}", e))?;
+    Ok(())
+}
+
+/// Adds an MCP server mid-task. Actor servers are spawned by us so they can
+/// be stopped on removal, and chat-state is respawned to pick up the tools.
+fn add_mcp_server(task_state: &mut TaskManagerState, mut server: McpServer) -> Result<(), String> {
+    let name = server
+        .name
+        .clone()
+        .ok_or_else(|| "An added MCP server needs a name".to_string())?;
+    let mut chat_servers = chat_mcp_servers(task_state)?;
+
+    let name_taken = chat_servers
+        .iter()
+        .any(|existing| existing.name.as_deref() == Some(name.as_str()))
+        || task_state.mcp_pool.iter().any(|pooled| pooled.name == name)
+        || task_state
+            .mcp_proxy
+            .as_ref()
+            .is_some_and(|mcp_proxy| mcp_proxy.has_server(&name));
+    if name_taken {
+        return Err(format!("An MCP server named {} already exists", name));
+    }
+
+    // As at init, calls to a stdio server would skip the approval step
+    if task_state.mode == TaskMode::Supervised && matches!(server.config, McpConfig::StdPipe(_)) {
+        return Err("supervised mode can't be used with stdio MCP servers".to_string());
+    }
+
+    mcp::validate_servers(std::slice::from_ref(&server))?;
+    task_state.metadata.add_to_server(&mut server);
+    info(&format!("Adding MCP server {}", name));
+
+    match mcp::SharedMcpServer::from_mcp_server(name.clone(), &server) {
+        Some(actor_server) => {
+            let spawned = actor_server.actor_id.is_none();
+            let actor_server = mcp::start_shared_servers(&[actor_server])?.remove(0);
+            if spawned && let Some(actor_id) = &actor_server.actor_id {
+                task_state
+                    .owned_mcp_actors
+                    .insert(name.clone(), actor_id.clone());
+            }
+
+            match &mut task_state.mcp_proxy {
+                Some(mcp_proxy) => mcp_proxy.add_server(actor_server),
+                None => chat_servers.push(actor_server.to_mcp_server()),
+            }
+        }
+        None => chat_servers.push(server),
+    }
+
+    set_chat_mcp_servers(task_state, &chat_servers)?;
+    let chat_config = chat_state_config(task_state);
+    respawn_chat_state(task_state, &chat_config)
+}
+
+/// Removes an MCP server by name, stopping its actor if we spawned it
+fn remove_mcp_server(task_state: &mut TaskManagerState, name: &str) -> Result<(), String> {
+    let mut chat_servers = chat_mcp_servers(task_state)?;
+    let server_count = chat_servers.len();
+    chat_servers.retain(|server| server.name.as_deref() != Some(name));
+    let removed_from_chat = chat_servers.len() != server_count;
+
+    let removed_from_proxy = task_state
+        .mcp_proxy
+        .as_mut()
+        .and_then(|mcp_proxy| mcp_proxy.remove_server(name))
+        .is_some();
+
+    if !removed_from_chat && !removed_from_proxy {
+        return Err(format!("No MCP server named {}", name));
+    }
+    info(&format!("Removing MCP server {}", name));
+
+    set_chat_mcp_servers(task_state, &chat_servers)?;
+    let chat_config = chat_state_config(task_state);
+    respawn_chat_state(task_state, &chat_config)?;
+
+    // Stopped only once the new chat-state no longer uses it
+    if let Some(actor_id) = task_state.owned_mcp_actors.remove(name) {
+        if let Err(e) = stop_child(&actor_id) {
+            warn(&format!("Failed to stop MCP server {}: {}", name, e));
+        }
+        task_state
+            .retired_children
+            .push(("mcp_server".to_string(), actor_id));
+    }
+    Ok(())
+}
+
+fn handle_task_complete(task_state: &mut TaskManagerState, completion: TaskComplete) {
+    if let Some(output_schema) = &task_state.output_schema {
+        let errors = match &completion.result {
+            Some(result) => schema::validate(output_schema, result),
+            None => vec!["$: task_complete was called without a result".to_string()],
+        };
+
+        if !errors.is_empty() {
+            warn(&format!("Task result failed validation: {:?}", errors));
+            reject_result(task_state, &errors);
+            return;
+        }
+    }
+
+    task_state.result = completion.result;
+
+    if let Some(verifier_config) = task_state.verifier.clone()
+        && !verify_result(task_state, &verifier_config)
+    {
+        return;
+    }
+
+    complete_task(task_state);
+}
+
+/// Accepts `task_state.result`: records the completion and finishes the task
+fn complete_task(task_state: &mut TaskManagerState) {
+    task_state.completions.push(CompletionRecord {
+        result: task_state.result.clone(),
+        verdict: task_state.verdict.clone(),
+        vote: task_state.current_vote(),
+        completed_at: now(),
+    });
+    task_state.set_status(TaskStatus::Completed);
+    task_state.emit(&TaskEvent::TaskCompleted);
+    finish_task(task_state);
+}
+
+/// Moves a completed task back to running with a follow-up message. The
+/// model keeps its context; earlier results stay in `completions`.
+fn reopen(task_state: &mut TaskManagerState, message: Message) -> Result<(), String> {
+    if task_state.status != TaskStatus::Completed {
+        return Err(format!(
+            "Only completed tasks can be reopened, this one is {:?}",
+            task_state.status
+        ));
+    }
+    if let TaskManagerResponse::Error { message } = forward_message(task_state, message) {
+        return Err(message);
+    }
+
+    info(&format!(
+        "Reopening task after {} completions",
+        task_state.completions.len()
+    ));
+    task_state.result = None;
+    task_state.verdict = None;
+    task_state.output_attempts = 0;
+    task_state.verifier_rejections = 0;
+    task_state.idle_nudges = 0;
+    task_state.set_status(TaskStatus::Running);
+    schedule_generation(task_state)
+}
+
+/// Sends validation errors back to the model so it can retry, or fails the
+/// task once the retries are used up
+fn reject_result(task_state: &mut TaskManagerState, errors: &[String]) {
+    task_state.output_attempts += 1;
+    if task_state.output_attempts > task_state.output_max_retries {
+        warn(&format!(
+            "Result still invalid after {} retries, failing task",
+            task_state.output_max_retries
+        ));
+        task_state.set_status(TaskStatus::Failed);
+        finish_task(task_state);
+        return;
+    }
+
+    let feedback = format!(
+        "The result you passed to task_complete does not match the required output schema:\n- {}\n\nPlease fix the result and call task_complete again.",
+        errors.join("\n- ")
+    );
+    send_feedback(task_state, feedback);
+}
+
+/// Runs the verifier on the finished task. Returns whether the completion
+/// should be accepted; on rejection the feedback has already been sent to
+/// the model, or the task has been failed if it was rejected too often.
+fn verify_result(task_state: &mut TaskManagerState, config: &verifier::VerifierConfig) -> bool {
+    let transcript = fetch_transcript(task_state).unwrap_or_else(|e| {
+        warn(&format!("Failed to fetch transcript for verifier: {}", e));
+        vec![]
+    });
+    let current_model_proxy = task_state
+        .model_chain
+        .get(task_state.model_index)
+        .cloned()
+        .unwrap_or_else(models::default_model_proxy);
+
+    let verdict = verifier::verify(
+        config,
+        &transcript,
+        task_state.result.as_ref(),
+        &current_model_proxy,
+        &task_state.model_presets,
+        task_state.verifier_rejections,
+    );
+    info(&format!("Verifier verdict: {:?}", verdict));
+    task_state.verdict = Some(verdict.clone());
+
+    if verdict.passed {
+        return true;
+    }
+
+    let max_rejections = config
+        .max_rejections
+        .unwrap_or(DEFAULT_VERIFIER_MAX_REJECTIONS);
+    if task_state.verifier_rejections >= max_rejections {
+        warn(&format!(
+            "Verifier rejected the result {} times, failing task",
+            max_rejections + 1
+        ));
+        task_state.set_status(TaskStatus::Failed);
+        finish_task(task_state);
+        return false;
+    }
+
+    task_state.verifier_rejections += 1;
+    let feedback = format!(
+        "A reviewer checked your work and did not accept it:\n{}\n\nPlease address the feedback and call task_complete again.",
+        verdict
+            .feedback
+            .as_deref()
+            .unwrap_or("No feedback was given.")
+    );
+    send_feedback(task_state, feedback);
+    false
+}
+
+/// Starts a new task from our transcript up to `at_message_index`, as our
+/// child, and leaves this one running. With `config_only` the fork's config
+/// is returned instead of spawned.
+fn fork_task(
+    task_state: &mut TaskManagerState,
+    at_message_index: usize,
+    overrides: Option<&Value>,
+    config_only: bool,
+) -> Result<TaskManagerResponse, String> {
+    let transcript = fetch_transcript(task_state)?;
+    let config = fork::fork_config(
+        &task_state.task_config,
+        &task_state.metadata,
+        &task_state.mcp_pool,
+        &transcript,
+        at_message_index,
+        overrides,
+    )?;
+    if config_only {
+        return Ok(TaskManagerResponse::Forked {
+            actor_id: None,
+            config: secrets::mask(&config),
+        });
+    }
+
+    let config_bytes =
+        to_vec(&config).map_err(|e| format!("Failed to serialize fork config: {}", e))?;
+    let actor_id = spawn(TASK_MANAGER_MANIFEST_PATH, Some(&config_bytes))
+        .map_err(|e| format!("Failed to spawn fork: {}", e))?;
+    logging::event(
+        LogLevel::Info,
+        "task_forked",
+        &format!("Forked at message {}", at_message_index),
+        serde_json::json!({ "fork_actor_id": actor_id, "at_message_index": at_message_index }),
+    );
+
+    let start_data = to_vec(&TaskManagerRequest::StartChat)
+        .map_err(|e| format!("Failed to serialize StartChat: {}", e))?;
+    request(&actor_id, &start_data).map_err(|e| format!("Failed to start fork: {}", e))?;
+
+    task_state.forks.push(actor_id.clone());
+    Ok(TaskManagerResponse::Forked {
+        actor_id: Some(actor_id),
+        config: secrets::mask(&config),
+    })
+}
+
+/// Starts the child task managers that run copies of this task from the
+/// same starting point: one per raced model, or one per sample
+fn start_children(task_state: &mut TaskManagerState) -> Result<(), String> {
+    if let Some(race) = task_state.race.as_mut() {
+        if race.started() {
+            return Err("The race has already started".to_string());
+        }
+        race.start(
+            &task_state.actor_id,
+            &task_state.task_config,
+            &task_state.metadata,
+            &task_state.mcp_pool,
+            &task_state.history,
+        )?;
+        info(&format!("Racing {} models", race.racers.len()));
+    }
+    if let Some(samples) = task_state.samples.as_mut() {
+        if samples.started() {
+            return Err("Sampling has already started".to_string());
+        }
+        samples.start(
+            &task_state.actor_id,
+            &task_state.task_config,
+            &task_state.metadata,
+            &task_state.mcp_pool,
+            &task_state.history,
+        )?;
+        info(&format!("Sampling the task {} times", samples.count));
+    }
+    task_state.set_status(TaskStatus::Running);
+    Ok(())
+}
+
+/// A racer or sample that failed or exited without reporting is counted as
+/// failed, so the race or vote doesn't wait on it forever
+fn child_gone(task_state: &mut TaskManagerState, actor_id: &str) {
+    if task_state.race.is_some() {
+        race_finished(task_state, actor_id, TaskStatus::Failed, None);
+    } else if task_state.samples.is_some() {
+        sample_finished(task_state, actor_id, TaskStatus::Failed, None);
+    }
+}
+
+/// Routes a racer or sample finishing to the race or the vote
+fn handle_child_notification(
+    task_state: &mut TaskManagerState,
+    notification: notify::Notification,
+) {
+    let (actor_id, status, result) = match notification {
+        notify::Notification::TaskFinished {
+            actor_id,
+            status,
+            result,
+            ..
+        } => (actor_id, status, result),
+        // A stalled child is done as far as we're concerned
+        notify::Notification::TaskStalled { actor_id, .. } => (actor_id, TaskStatus::Stalled, None),
+    };
+    if task_state.race.is_some() {
+        race_finished(task_state, &actor_id, status, result);
+    } else if task_state.samples.is_some() {
+        sample_finished(task_state, &actor_id, status, result);
+    }
+}
+
+/// Records a racer finishing and, once the race is decided, adopts the
+/// winner or fails the task if no racer completed
+fn race_finished(
+    task_state: &mut TaskManagerState,
+    actor_id: &str,
+    status: TaskStatus,
+    result: Option<Value>,
+) {
+    let Some(race) = task_state.race.as_mut() else {
+        return;
+    };
+    let winner = match race.record(actor_id, status, result) {
+        None | Some(race::Outcome::Running) => return,
+        Some(race::Outcome::Won(winner)) => winner,
+        Some(race::Outcome::Judge(candidates)) => {
+            let current_model_proxy = task_state
+                .model_chain
+                .get(task_state.model_index)
+                .cloned()
+                .unwrap_or_else(models::default_model_proxy);
+            race.judge(
+                &candidates,
+                task_state.initial_message.as_deref(),
+                &current_model_proxy,
+                &task_state.model_presets,
+            )
+        }
+        Some(race::Outcome::Lost) => {
+            warn("No racer completed the task");
+            race.stop_racers();
+            task_state.set_status(TaskStatus::Failed);
+            finish_task(task_state);
+            return;
+        }
+    };
+
+    if let Err(e) = finish_race(task_state, winner) {
+        error(&format!("Failed to adopt the race winner: {}", e));
+        task_state.set_status(TaskStatus::Failed);
+        finish_task(task_state);
+    }
+}
+
+/// Takes over the winner's transcript and result, and stops every racer
+fn finish_race(task_state: &mut TaskManagerState, winner: usize) -> Result<(), String> {
+    let Some(race) = task_state.race.as_mut() else {
+        return Ok(());
+    };
+    race.winner = Some(winner);
+    let racer = race.racers[winner].clone();
+    let reason = race.reason.clone();
+
+    let adopted = adopt_transcript(task_state, &racer.actor_id);
+    if let Some(race) = &task_state.race {
+        race.stop_racers();
+    }
+    adopted?;
+
+    logging::event(
+        LogLevel::Info,
+        "race_won",
+        &format!("Racer {} on {} won the race", racer.actor_id, racer.model),
+        serde_json::json!({ "racer_actor_id": racer.actor_id, "model": racer.model, "reason": reason }),
+    );
+    task_state.result = racer.result;
+    complete_task(task_state);
+    Ok(())
+}
+
+/// Records a sample finishing and, once all have, completes the task with
+/// the majority answer, or fails it if no sample completed
+fn sample_finished(
+    task_state: &mut TaskManagerState,
+    actor_id: &str,
+    status: TaskStatus,
+    result: Option<Value>,
+) {
+    let Some(samples) = task_state.samples.as_mut() else {
+        return;
+    };
+    let Some(vote) = samples.record(actor_id, status, result) else {
+        return;
+    };
+    let Some(vote) = vote else {
+        warn("No sample completed the task");
+        samples.stop_samples();
+        task_state.set_status(TaskStatus::Failed);
+        finish_task(task_state);
+        return;
+    };
+
+    // The transcript is taken from the first sample that gave the answer
+    let actor_id = samples.samples[vote.sample].actor_id.clone();
+    let adopted = adopt_transcript(task_state, &actor_id);
+    if let Some(samples) = &task_state.samples {
+        samples.stop_samples();
+    }
+    if let Err(e) = adopted {
+        error(&format!("Failed to adopt the sampled transcript: {}", e));
+        task_state.set_status(TaskStatus::Failed);
+        finish_task(task_state);
+        return;
+    }
+
+    logging::event(
+        LogLevel::Info,
+        "samples_voted",
+        &format!(
+            "{} of {} samples agreed on the answer",
+            vote.votes, vote.samples
+        ),
+        serde_json::json!({ "votes": vote.votes, "completed": vote.completed, "samples": vote.samples, "agreement": vote.agreement }),
+    );
+    task_state.result = vote.answer;
+    complete_task(task_state);
+}
+
+/// Copies the transcript and model turns of a child task manager into our
+/// chat-state
+fn adopt_transcript(task_state: &mut TaskManagerState, actor_id: &str) -> Result<(), String> {
+    let request_data = to_vec(&TaskManagerRequest::GetTranscript)
+        .map_err(|e| format!("Failed to serialize GetTranscript: {}", e))?;
+    let response_data = request(actor_id, &request_data)
+        .map_err(|e| format!("Failed to fetch transcript of {}: {}", actor_id, e))?;
+    let (messages, model_turns) = match from_slice::<TaskManagerResponse>(&response_data) {
+        Ok(TaskManagerResponse::Transcript {
+            messages,
+            model_turns,
+        }) => (messages, model_turns),
+        Ok(other) => return Err(format!("Unexpected transcript response: {:?}", other)),
+        Err(e) => return Err(format!("Failed to parse transcript response: {}", e)),
+    };
+
+    let chat_actor_id = task_state.get_chat_state_actor_id()?.clone();
+    for message in messages {
+        if let ChatStateResponse::Error { error } =
+            request_chat_state(&chat_actor_id, &ChatStateRequest::AddMessage { message })?
+        {
+            return Err(format!(
+                "Chat state actor returned error {}: {}",
+                error.code, error.message
+            ));
+        }
+    }
+    task_state.model_turns = model_turns;
+    sync_transcript(task_state);
+    Ok(())
+}
+
+/// Answers a tool call held in supervised mode, making the call if it is
+/// approved. Chat-state is still waiting on its original request.
+fn decide_tool_call(
+    task_state: &mut TaskManagerState,
+    request_id: &str,
+    approved: bool,
+    reason: Option<String>,
+) -> Result<(), String> {
+    let mcp_proxy = task_state
+        .mcp_proxy
+        .as_mut()
+        .ok_or_else(|| "Tool calls are only held in supervised mode".to_string())?;
+    let response = if approved {
+        mcp_proxy.approve(request_id, &mut task_state.audit_log)?
+    } else {
+        mcp_proxy.reject(request_id, reason, &mut task_state.audit_log)?
+    };
+
+    let response_data =
+        to_vec(&response).map_err(|e| format!("Failed to serialize response: {}", e))?;
+    respond_to_request(request_id, &response_data)
+}
+
+/// Adds a message to the transcript without starting a turn
+fn forward_message(task_state: &mut TaskManagerState, message: Message) -> TaskManagerResponse {
+    let response = match task_state.get_chat_state_actor_id() {
+        Ok(chat_actor_id) => {
+            let add_message_request = ChatStateRequest::AddMessage { message };
+
+            match to_vec(&add_message_request) {
+                Ok(request_data) => match send(chat_actor_id, &request_data) {
+                    Ok(_) => {
+                        debug("Message forwarded to chat state actor");
+                        TaskManagerResponse::Success
+                    }
+                    Err(e) => {
+                        let error_msg = format!("Failed to forward message: {:?}", e);
+                        error(&error_msg);
+                        TaskManagerResponse::Error { message: error_msg }
+                    }
+                },
+                Err(e) => {
+                    let error_msg = format!("Failed to serialize message: {}", e);
+                    error(&error_msg);
+                    TaskManagerResponse::Error { message: error_msg }
+                }
+            }
+        }
+        Err(e) => TaskManagerResponse::Error { message: e },
+    };
+
+    // While proxying, chat-state may be mid-generation and waiting on us
+    if matches!(response, TaskManagerResponse::Success) && task_state.mcp_proxy.is_none() {
+        sync_transcript(task_state);
+    }
+    response
+}
+
+/// Adds a user message and resumes generation so the model can respond to it
+fn send_feedback(task_state: &mut TaskManagerState, feedback: String) {
+    let message = Message {
+        role: Role::User,
+        content: vec![MessageContent::Text { text: feedback }],
+    };
+
+    match forward_message(task_state, message) {
+        TaskManagerResponse::Success => {
+            if let Err(e) = schedule_generation(task_state) {
+                warn(&format!("Failed to schedule generation: {}", e));
+            }
+        }
+        response => warn(&format!("Failed to send feedback: {:?}", response)),
+    }
+}
+
+/// Notifies the configured targets, then shuts down with the completion
+/// payload if auto exit is enabled
+fn finish_task(task_state: &TaskManagerState) {
+    // The response lands in our event chain, which is where a replay of
+    // this run reads the model and tool output from
+    if let Err(e) = fetch_transcript(task_state) {
+        warn(&format!("Failed to record the final transcript: {}", e));
+    }
+    notify_finished(task_state);
+
+    if !task_state.exit_on_completion {
+        info("Task finished, but auto exit is disabled");
+        return;
+    }
+
+    info("Auto exit on completion is enabled, shutting down task manager");
+    shutdown_with_payload(task_state);
+}
+
+/// Shuts down, handing our supervisor the completion payload
+fn shutdown_with_payload(task_state: &TaskManagerState) {
+    let payload = CompletionPayload {
+        metadata: task_state.metadata.clone(),
+        status: task_state.status,
+        result: task_state.result.clone(),
+        verdict: task_state.verdict.clone(),
+        vote: task_state.current_vote(),
+    };
+    let _ = shutdown(to_vec(&payload).ok().as_deref());
+}
+
+fn notify_finished(task_state: &TaskManagerState) {
+    if task_state.notify.is_empty() {
+        return;
+    }
+    if task_state.replay.is_some() {
+        info("Not sending TaskFinished notifications for a replay");
+        return;
+    }
+
+    let notification = notify::Notification::TaskFinished {
+        task_id: task_state.metadata.task_id.clone(),
+        actor_id: task_state.actor_id.clone(),
+        metadata: task_state.metadata.clone(),
+        status: task_state.status,
+        result: task_state.result.clone(),
+        verdict: task_state.verdict.clone(),
+        usage: notify::TaskUsage {
+            messages: task_state.seen_messages,
+            model_turns: task_state.model_turns.len(),
+            tool_calls: task_state.audit_log.len(),
+        },
+    };
+    notify::notify(&task_state.notify, &notification);
+}
+
+fn notify_stalled(task_state: &TaskManagerState) {
+    if task_state.notify.is_empty() {
+        return;
+    }
+
+    let notification = notify::Notification::TaskStalled {
+        task_id: task_state.metadata.task_id.clone(),
+        actor_id: task_state.actor_id.clone(),
+        metadata: task_state.metadata.clone(),
+        nudges: task_state.idle_nudges,
+    };
+    notify::notify(&task_state.notify, &notification);
 }
 
 fn spawn_chat_state_actor(config: &Value) -> Result<String, String> {
-    log("Spawning chat-state actor...");
+    debug("Spawning chat-state actor...");
 
-    let config_bytes = to_vec(config).map_err(|e| format!("Failed to serialize config: {}", e))?;
+    let config = secrets::resolve(config)?;
+    let config_bytes = to_vec(&config).map_err(|e| format!("Failed to serialize config: {}", e))?;
 
     match spawn(CHAT_STATE_MANIFEST_PATH, Some(&config_bytes)) {
         Ok(actor_id) => {
-            log(&format!(
+            info(&format!(
                 "Successfully spawned chat-state actor: {}",
                 actor_id
             ));
@@ -587,7 +2637,7 @@ fn spawn_chat_state_actor(config: &Value) -> Result<String, String> {
         }
         Err(e) => {
             let error_msg = format!("Failed to spawn chat-state actor: {:?}", e);
-            log(&error_msg);
+            error(&error_msg);
             Err(error_msg)
         }
     }
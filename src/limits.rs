@@ -0,0 +1,231 @@
+use crate::protocol::McpError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// Server-defined JSON-RPC error codes for calls the proxy refuses
+pub const QUOTA_EXCEEDED: i32 = -32001;
+pub const RATE_LIMITED: i32 = -32002;
+
+const MINUTE_MS: u64 = 60_000;
+
+/// Call limits keyed by MCP server name and by tool name
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ToolLimits {
+    #[serde(default)]
+    pub servers: HashMap<String, CallLimit>,
+    #[serde(default)]
+    pub tools: HashMap<String, CallLimit>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CallLimit {
+    /// Calls allowed over the whole task
+    pub max_calls: Option<u32>,
+    /// Calls allowed in any 60 second window
+    pub max_calls_per_minute: Option<u32>,
+}
+
+/// Calls made so far, kept alongside the limits
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CallCounts {
+    servers: HashMap<String, CallCount>,
+    tools: HashMap<String, CallCount>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+struct CallCount {
+    total: u32,
+    /// Timestamps of the calls made in the last minute
+    recent: Vec<u64>,
+}
+
+/// Call counts as reported by the status request
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ToolUsage {
+    pub servers: HashMap<String, UsageCount>,
+    pub tools: HashMap<String, UsageCount>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UsageCount {
+    pub total: u32,
+    pub last_minute: u32,
+}
+
+impl CallCount {
+    fn prune(&mut self, now: u64) {
+        self.recent
+            .retain(|timestamp| now.saturating_sub(*timestamp) < MINUTE_MS);
+    }
+
+    fn check(&self, scope: &str, name: &str, limit: &CallLimit) -> Result<(), McpError> {
+        if let Some(max_calls) = limit.max_calls
+            && self.total >= max_calls
+        {
+            return Err(limit_error(
+                QUOTA_EXCEEDED,
+                format!(
+                    "Call quota exceeded for {} {}: {} calls allowed per task",
+                    scope, name, max_calls
+                ),
+                scope,
+                name,
+            ));
+        }
+
+        if let Some(max_per_minute) = limit.max_calls_per_minute
+            && self.recent.len() as u32 >= max_per_minute
+        {
+            return Err(limit_error(
+                RATE_LIMITED,
+                format!(
+                    "Rate limit exceeded for {} {}: {} calls allowed per minute",
+                    scope, name, max_per_minute
+                ),
+                scope,
+                name,
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn record(&mut self, now: u64) {
+        self.total += 1;
+        self.recent.push(now);
+    }
+
+    fn usage(&self, now: u64) -> UsageCount {
+        UsageCount {
+            total: self.total,
+            last_minute: self
+                .recent
+                .iter()
+                .filter(|timestamp| now.saturating_sub(**timestamp) < MINUTE_MS)
+                .count() as u32,
+        }
+    }
+}
+
+impl CallCounts {
+    /// Checks a call against the server and tool limits and counts it if it
+    /// is allowed. Refused calls are not counted.
+    pub fn admit(
+        &mut self,
+        limits: &ToolLimits,
+        server: &str,
+        tool: &str,
+        now: u64,
+    ) -> Result<(), McpError> {
+        let server_count = self.servers.entry(server.to_string()).or_default();
+        server_count.prune(now);
+        let tool_count = self.tools.entry(tool.to_string()).or_default();
+        tool_count.prune(now);
+
+        if let Some(limit) = limits.servers.get(server) {
+            self.servers[server].check("server", server, limit)?;
+        }
+        if let Some(limit) = limits.tools.get(tool) {
+            self.tools[tool].check("tool", tool, limit)?;
+        }
+
+        if let Some(count) = self.servers.get_mut(server) {
+            count.record(now);
+        }
+        if let Some(count) = self.tools.get_mut(tool) {
+            count.record(now);
+        }
+        Ok(())
+    }
+
+    pub fn usage(&self, now: u64) -> ToolUsage {
+        ToolUsage {
+            servers: self
+                .servers
+                .iter()
+                .map(|(name, count)| (name.clone(), count.usage(now)))
+                .collect(),
+            tools: self
+                .tools
+                .iter()
+                .map(|(name, count)| (name.clone(), count.usage(now)))
+                .collect(),
+        }
+    }
+}
+
+fn limit_error(code: i32, message: String, scope: &str, name: &str) -> McpError {
+    McpError {
+        code,
+        message,
+        data: Some(serde_json::json!({
+            "scope": scope,
+            "name": name,
+        })),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(server: CallLimit, tool: CallLimit) -> ToolLimits {
+        ToolLimits {
+            servers: HashMap::from([("fs".to_string(), server)]),
+            tools: HashMap::from([("write_file".to_string(), tool)]),
+        }
+    }
+
+    #[test]
+    fn refuses_calls_over_the_task_quota() {
+        let limits = limits(
+            CallLimit::default(),
+            CallLimit {
+                max_calls: Some(2),
+                max_calls_per_minute: None,
+            },
+        );
+        let mut counts = CallCounts::default();
+        assert!(counts.admit(&limits, "fs", "write_file", 0).is_ok());
+        assert!(counts.admit(&limits, "fs", "write_file", 1).is_ok());
+        let error = counts.admit(&limits, "fs", "write_file", 2).unwrap_err();
+        assert_eq!(error.code, QUOTA_EXCEEDED);
+        // Other tools on the same server are unaffected
+        assert!(counts.admit(&limits, "fs", "read_file", 3).is_ok());
+    }
+
+    #[test]
+    fn rate_limit_frees_up_after_a_minute() {
+        let limits = limits(
+            CallLimit {
+                max_calls: None,
+                max_calls_per_minute: Some(2),
+            },
+            CallLimit::default(),
+        );
+        let mut counts = CallCounts::default();
+        assert!(counts.admit(&limits, "fs", "read_file", 0).is_ok());
+        assert!(counts.admit(&limits, "fs", "write_file", 1_000).is_ok());
+        let error = counts.admit(&limits, "fs", "read_file", 2_000).unwrap_err();
+        assert_eq!(error.code, RATE_LIMITED);
+        assert!(counts.admit(&limits, "fs", "read_file", MINUTE_MS).is_ok());
+    }
+
+    #[test]
+    fn refused_calls_are_not_counted() {
+        let limits = limits(
+            CallLimit {
+                max_calls: Some(1),
+                max_calls_per_minute: None,
+            },
+            CallLimit::default(),
+        );
+        let mut counts = CallCounts::default();
+        assert!(counts.admit(&limits, "fs", "read_file", 0).is_ok());
+        assert!(counts.admit(&limits, "fs", "read_file", 1).is_err());
+
+        let usage = counts.usage(2);
+        assert_eq!(usage.servers["fs"].total, 1);
+        assert_eq!(usage.tools["read_file"].total, 1);
+    }
+}
@@ -0,0 +1,150 @@
+use crate::bindings::theater::simple::runtime;
+use crate::metadata::TaskMetadata;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::cell::RefCell;
+
+/// Config keys whose values never reach the log
+const DEFAULT_REDACTED_KEYS: &[&str] = &[
+    "system_prompt",
+    "api_key",
+    "authorization",
+    "password",
+    "secret",
+    "token",
+    "env",
+    crate::secrets::SECRET_KEY,
+];
+
+const REDACTED: &str = "[redacted]";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum LogLevel {
+    Trace,
+    Debug,
+    #[default]
+    Info,
+    Warn,
+    Error,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct LogConfig {
+    /// Lines below this level are dropped
+    #[serde(default)]
+    pub level: LogLevel,
+    /// Keys redacted in addition to the defaults
+    #[serde(default)]
+    pub redact: Vec<String>,
+}
+
+#[derive(Default)]
+struct LogContext {
+    metadata: Option<TaskMetadata>,
+    config: LogConfig,
+}
+
+thread_local! {
+    /// Set once the state of the handler call in progress is loaded
+    static CONTEXT: RefCell<LogContext> = RefCell::new(LogContext::default());
+}
+
+/// Tags the log lines of the current handler call with the task's metadata
+/// and applies its log config
+pub fn set_context(metadata: &TaskMetadata, config: &LogConfig) {
+    CONTEXT.with(|context| {
+        *context.borrow_mut() = LogContext {
+            metadata: Some(metadata.clone()),
+            config: config.clone(),
+        }
+    });
+}
+
+pub fn debug(message: &str) {
+    write(LogLevel::Debug, None, message, None);
+}
+
+pub fn info(message: &str) {
+    write(LogLevel::Info, None, message, None);
+}
+
+pub fn warn(message: &str) {
+    write(LogLevel::Warn, None, message, None);
+}
+
+pub fn error(message: &str) {
+    write(LogLevel::Error, None, message, None);
+}
+
+/// Logs a named event with structured fields, which are redacted first
+pub fn event(level: LogLevel, event: &str, message: &str, fields: Value) {
+    write(level, Some(event), message, Some(fields));
+}
+
+fn write(level: LogLevel, event: Option<&str>, message: &str, fields: Option<Value>) {
+    CONTEXT.with(|context| {
+        let context = context.borrow();
+        if level < context.config.level {
+            return;
+        }
+
+        let mut line = Map::new();
+        line.insert(
+            "level".to_string(),
+            serde_json::to_value(level).unwrap_or_default(),
+        );
+        if let Some(metadata) = &context.metadata {
+            line.insert("task_id".to_string(), Value::from(metadata.task_id.clone()));
+            if let Some(correlation_id) = &metadata.correlation_id {
+                line.insert(
+                    "correlation_id".to_string(),
+                    Value::from(correlation_id.clone()),
+                );
+            }
+            if let Some(parent_task_id) = &metadata.parent_task_id {
+                line.insert(
+                    "parent_task_id".to_string(),
+                    Value::from(parent_task_id.clone()),
+                );
+            }
+        }
+        if let Some(event) = event {
+            line.insert("event".to_string(), Value::from(event));
+        }
+        line.insert("message".to_string(), Value::from(message));
+        if let Some(fields) = fields {
+            line.insert(
+                "fields".to_string(),
+                redact(&fields, &context.config.redact),
+            );
+        }
+
+        runtime::log(&Value::Object(line).to_string());
+    });
+}
+
+/// Replaces the values of sensitive keys, at any depth
+pub fn redact(value: &Value, extra_keys: &[String]) -> Value {
+    match value {
+        Value::Object(object) => Value::Object(
+            object
+                .iter()
+                .map(|(key, value)| {
+                    let sensitive =
+                        DEFAULT_REDACTED_KEYS.contains(&key.as_str()) || extra_keys.contains(key);
+                    let value = if sensitive {
+                        Value::from(REDACTED)
+                    } else {
+                        redact(value, extra_keys)
+                    };
+                    (key.clone(), value)
+                })
+                .collect(),
+        ),
+        Value::Array(items) => {
+            Value::Array(items.iter().map(|item| redact(item, extra_keys)).collect())
+        }
+        other => other.clone(),
+    }
+}
@@ -0,0 +1,157 @@
+use crate::bindings::theater::simple::filesystem::{CommandResult, execute_command, path_exists};
+use crate::bindings::theater::simple::supervisor::spawn;
+use crate::logging::{debug, info};
+use crate::protocol::{ActorMcpConfig, McpConfig, McpServer, StdPipeMcpConfig};
+use crate::secrets;
+use mcp_protocol::tool::Tool;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, to_vec};
+
+/// Checks the configured MCP servers before chat-state is asked to start them
+pub fn validate_servers(servers: &[McpServer]) -> Result<(), String> {
+    for server in servers {
+        if let McpConfig::StdPipe(config) = &server.config {
+            validate_stdio_server(config)?;
+        }
+    }
+    Ok(())
+}
+
+fn validate_stdio_server(config: &StdPipeMcpConfig) -> Result<(), String> {
+    debug(&format!("Validating stdio MCP server: {}", config.command));
+
+    if config.command.trim().is_empty() {
+        return Err("Stdio MCP server has an empty command".to_string());
+    }
+
+    if config.startup_timeout == Some(0) {
+        return Err(format!(
+            "Stdio MCP server {} has a startup_timeout of 0",
+            config.command
+        ));
+    }
+
+    if let Some(cwd) = &config.cwd
+        && !path_exists(cwd).map_err(|e| format!("Failed to check cwd {}: {}", cwd, e))?
+    {
+        return Err(format!(
+            "Working directory {} for stdio MCP server {} does not exist",
+            cwd, config.command
+        ));
+    }
+
+    if !command_exists(&config.command, config.cwd.as_deref())? {
+        return Err(format!(
+            "Command for stdio MCP server not found: {}",
+            config.command
+        ));
+    }
+
+    Ok(())
+}
+
+/// Paths are checked directly; bare command names are looked up on the PATH
+fn command_exists(command: &str, cwd: Option<&str>) -> Result<bool, String> {
+    if command.contains('/') {
+        return path_exists(command).map_err(|e| format!("Failed to check {}: {}", command, e));
+    }
+
+    let result = execute_command(cwd.unwrap_or("."), "which", &[command.to_string()])
+        .map_err(|e| format!("Failed to look up {}: {}", command, e))?;
+
+    match result {
+        CommandResult::Success(success) => Ok(success.exit_code == 0),
+        CommandResult::Error(error) => {
+            Err(format!("Failed to look up {}: {}", command, error.message))
+        }
+    }
+}
+
+/// An MCP actor owned by the task manager rather than by chat-state, so it
+/// stays up across chat-state respawns and can be handed to other tasks.
+/// Either points at an already-running actor by id or is spawned from a
+/// manifest when the task manager starts.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SharedMcpServer {
+    pub name: String,
+    pub actor_id: Option<String>,
+    pub manifest_path: Option<String>,
+    pub init_state: Option<Value>,
+    pub tools: Option<Vec<Tool>>,
+}
+
+impl SharedMcpServer {
+    /// An actor-based `mcp_servers` entry as a server we manage; stdio
+    /// servers are run by chat-state and have no actor to share
+    pub fn from_mcp_server(name: String, server: &McpServer) -> Option<Self> {
+        match &server.config {
+            McpConfig::Actor(config) => Some(Self {
+                name,
+                actor_id: server.actor_id.clone(),
+                manifest_path: Some(config.manifest_path.clone()),
+                init_state: config.init_state.clone(),
+                tools: server.tools.clone(),
+            }),
+            McpConfig::StdPipe(_) => None,
+        }
+    }
+
+    /// The entry handed to chat-state, which connects to the actor by id
+    pub fn to_mcp_server(&self) -> McpServer {
+        McpServer {
+            name: Some(self.name.clone()),
+            actor_id: self.actor_id.clone(),
+            config: McpConfig::Actor(ActorMcpConfig {
+                manifest_path: self.manifest_path.clone().unwrap_or_default(),
+                init_state: self.init_state.clone(),
+            }),
+            tools: self.tools.clone(),
+        }
+    }
+}
+
+/// Spawns the shared servers that aren't already running and returns the
+/// pool with every actor id filled in
+pub fn start_shared_servers(servers: &[SharedMcpServer]) -> Result<Vec<SharedMcpServer>, String> {
+    let mut pool = Vec::with_capacity(servers.len());
+
+    for server in servers {
+        let mut server = server.clone();
+        match (&server.actor_id, &server.manifest_path) {
+            (Some(actor_id), _) => {
+                info(&format!(
+                    "Using running MCP actor {} for shared server {}",
+                    actor_id, server.name
+                ));
+            }
+            (None, Some(manifest_path)) => {
+                let init_bytes = server
+                    .init_state
+                    .as_ref()
+                    .map(secrets::resolve)
+                    .transpose()?
+                    .as_ref()
+                    .map(to_vec)
+                    .transpose()
+                    .map_err(|e| format!("Failed to serialize init state: {}", e))?;
+                let actor_id = spawn(manifest_path, init_bytes.as_deref()).map_err(|e| {
+                    format!("Failed to spawn shared MCP server {}: {}", server.name, e)
+                })?;
+                info(&format!(
+                    "Spawned shared MCP server {}: {}",
+                    server.name, actor_id
+                ));
+                server.actor_id = Some(actor_id);
+            }
+            (None, None) => {
+                return Err(format!(
+                    "Shared MCP server {} needs either actor_id or manifest_path",
+                    server.name
+                ));
+            }
+        }
+        pool.push(server);
+    }
+
+    Ok(pool)
+}
@@ -0,0 +1,42 @@
+use crate::logging::warn;
+use crate::protocol::{McpConfig, McpServer};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Identifies a task across services; attached to logs, events, status
+/// responses, completion payloads and MCP server init state
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TaskMetadata {
+    pub task_id: String,
+    pub title: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub owner: Option<String>,
+    pub parent_task_id: Option<String>,
+    pub correlation_id: Option<String>,
+}
+
+impl TaskMetadata {
+    /// Adds the metadata to an init state under `task_metadata`. Init states
+    /// that aren't objects are left alone.
+    pub fn add_to_init_state(&self, init_state: Option<Value>) -> Option<Value> {
+        let mut init_state = init_state.unwrap_or_else(|| serde_json::json!({}));
+        match init_state.as_object_mut() {
+            Some(object) => {
+                object.insert(
+                    "task_metadata".to_string(),
+                    serde_json::to_value(self).unwrap_or_default(),
+                );
+            }
+            None => warn("MCP server init state is not an object, not adding task metadata"),
+        }
+        Some(init_state)
+    }
+
+    /// Passes the metadata to an actor MCP server through its init state
+    pub fn add_to_server(&self, server: &mut McpServer) {
+        if let McpConfig::Actor(config) = &mut server.config {
+            config.init_state = self.add_to_init_state(config.init_state.take());
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use crate::bindings::theater::simple::filesystem::read_file;
+use crate::logging::info;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, from_slice};
+use std::collections::HashMap;
+
+const DEFAULT_PROXY_MANIFEST_PATH: &str =
+    "https://github.com/colinrozzi/google-proxy/releases/latest/download/manifest.toml";
+const ANTHROPIC_PROXY_MANIFEST_PATH: &str =
+    "https://github.com/colinrozzi/anthropic-proxy/releases/latest/download/manifest.toml";
+const DEFAULT_MODEL: &str = "gemini-2.0-flash";
+
+/// A named bundle of model settings that configs can refer to by name
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ModelPreset {
+    pub manifest_path: String,
+    pub model: String,
+    pub temperature: Option<f64>,
+    pub max_tokens: Option<u32>,
+}
+
+impl ModelPreset {
+    pub fn model_proxy(&self) -> Value {
+        serde_json::json!({
+            "manifest_path": self.manifest_path,
+            "model": self.model,
+        })
+    }
+}
+
+pub type ModelPresets = HashMap<String, ModelPreset>;
+
+/// Records which model produced an assistant message in the transcript
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ModelTurn {
+    pub index: usize,
+    pub model: String,
+}
+
+pub fn default_model_proxy() -> Value {
+    serde_json::json!({
+        "manifest_path": DEFAULT_PROXY_MANIFEST_PATH,
+        "model": DEFAULT_MODEL,
+    })
+}
+
+fn preset(manifest_path: &str, model: &str, max_tokens: u32) -> ModelPreset {
+    ModelPreset {
+        manifest_path: manifest_path.to_string(),
+        model: model.to_string(),
+        temperature: Some(0.7),
+        max_tokens: Some(max_tokens),
+    }
+}
+
+pub fn builtin_presets() -> ModelPresets {
+    HashMap::from([
+        (
+            "fast".to_string(),
+            preset(DEFAULT_PROXY_MANIFEST_PATH, DEFAULT_MODEL, 8192),
+        ),
+        (
+            "cheap".to_string(),
+            preset(DEFAULT_PROXY_MANIFEST_PATH, "gemini-2.0-flash-lite", 4096),
+        ),
+        (
+            "smart".to_string(),
+            preset(
+                ANTHROPIC_PROXY_MANIFEST_PATH,
+                "claude-sonnet-4-20250514",
+                8192,
+            ),
+        ),
+    ])
+}
+
+/// Loads the built-in presets, extended or overridden by a JSON file mapping
+/// preset names to presets
+pub fn load_presets(path: Option<&str>) -> Result<ModelPresets, String> {
+    let mut presets = builtin_presets();
+
+    if let Some(path) = path {
+        info(&format!("Loading model presets from {}", path));
+        let data =
+            read_file(path).map_err(|e| format!("Failed to read presets {}: {}", path, e))?;
+        let file_presets: ModelPresets =
+            from_slice(&data).map_err(|e| format!("Failed to parse presets {}: {}", path, e))?;
+        presets.extend(file_presets);
+    }
+
+    Ok(presets)
+}
+
+pub fn get_preset<'a>(presets: &'a ModelPresets, name: &str) -> Result<&'a ModelPreset, String> {
+    presets
+        .get(name)
+        .ok_or_else(|| format!("Unknown model preset: {}", name))
+}
+
+/// Expands the configured `model_proxy` into an ordered fallback chain.
+/// Accepts a single proxy object or a list of them, where list entries may
+/// also be preset names; without one, the selected preset (or the default
+/// model) is used. The first entry is used until the provider fails, then
+/// the next one takes over.
+pub fn model_chain(
+    model_proxy: Option<&Value>,
+    preset: Option<&ModelPreset>,
+    presets: &ModelPresets,
+) -> Result<Vec<Value>, String> {
+    let fallback = || match preset {
+        Some(preset) => vec![preset.model_proxy()],
+        None => vec![default_model_proxy()],
+    };
+
+    let entries = match model_proxy {
+        Some(Value::Array(proxies)) if !proxies.is_empty() => proxies.clone(),
+        Some(Value::Array(_)) | None => fallback(),
+        Some(proxy) => vec![proxy.clone()],
+    };
+
+    entries
+        .into_iter()
+        .map(|entry| match entry {
+            Value::String(name) => get_preset(presets, &name).map(ModelPreset::model_proxy),
+            proxy => Ok(proxy),
+        })
+        .collect()
+}
+
+/// Short name for a proxy entry, used in logs and turn records
+pub fn model_label(model_proxy: &Value) -> String {
+    model_proxy
+        .get("model")
+        .or_else(|| model_proxy.get("manifest_path"))
+        .and_then(Value::as_str)
+        .unwrap_or("unknown")
+        .to_string()
+}
@@ -0,0 +1,71 @@
+use crate::TaskStatus;
+use crate::bindings::theater::simple::message_server_host::{send, send_on_channel};
+use crate::logging::{debug, warn};
+use crate::metadata::TaskMetadata;
+use crate::verifier::Verdict;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, to_vec};
+
+/// Who hears about the task finishing: an actor id, or `{"channel": id}`
+/// for an open channel
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum NotifyTarget {
+    Actor(String),
+    Channel { channel: String },
+}
+
+/// Sent to every notify target when the task completes, fails or is
+/// cancelled, and when it stalls
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type")]
+pub enum Notification {
+    TaskFinished {
+        task_id: String,
+        /// The task manager that sent the notification
+        actor_id: String,
+        metadata: TaskMetadata,
+        status: TaskStatus,
+        result: Option<Value>,
+        verdict: Option<Verdict>,
+        usage: TaskUsage,
+    },
+    /// The model kept stopping without completing the task. The task stays
+    /// up; a new message resumes it.
+    TaskStalled {
+        task_id: String,
+        actor_id: String,
+        metadata: TaskMetadata,
+        nudges: u32,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TaskUsage {
+    pub messages: usize,
+    pub model_turns: usize,
+    pub tool_calls: usize,
+}
+
+/// Sends the notification to each target. Failures are logged and don't
+/// stop the others from being notified.
+pub fn notify(targets: &[NotifyTarget], notification: &Notification) {
+    let data = match to_vec(notification) {
+        Ok(data) => data,
+        Err(e) => {
+            warn(&format!("Failed to serialize notification: {}", e));
+            return;
+        }
+    };
+
+    for target in targets {
+        let sent = match target {
+            NotifyTarget::Actor(actor_id) => send(actor_id, &data),
+            NotifyTarget::Channel { channel } => send_on_channel(channel, &data),
+        };
+        match sent {
+            Ok(()) => debug(&format!("Notified {:?}", target)),
+            Err(e) => warn(&format!("Failed to notify {:?}: {}", target, e)),
+        }
+    }
+}
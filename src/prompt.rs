@@ -0,0 +1,93 @@
+use crate::bindings::theater::simple::filesystem::read_file;
+use crate::bindings::theater::simple::store::{self, ContentRef};
+use crate::logging::debug;
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_SYSTEM_PROMPT: &str = "You are an AI assistant that helps users complete tasks efficiently. You have access to various tools and can help with a wide range of activities.";
+
+/// The configured system prompt: a single string, or fragments composed in order
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum SystemPromptConfig {
+    Text(String),
+    Fragments(Vec<PromptFragment>),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PromptFragment {
+    #[serde(flatten)]
+    pub source: FragmentSource,
+    #[serde(default)]
+    pub role: FragmentRole,
+    #[serde(default)]
+    pub order: i32,
+}
+
+/// Where a fragment's text comes from
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum FragmentSource {
+    Text(String),
+    File(String),
+    ContentRef { store_id: String, hash: String },
+}
+
+/// Fragments are grouped by role, in the order the variants are declared
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum FragmentRole {
+    Persona,
+    #[default]
+    Task,
+    Constraints,
+    ToolGuidance,
+}
+
+impl FragmentSource {
+    fn load(&self) -> Result<String, String> {
+        match self {
+            FragmentSource::Text(text) => Ok(text.clone()),
+            FragmentSource::File(path) => {
+                debug(&format!("Reading prompt fragment from {}", path));
+                let data = read_file(path)
+                    .map_err(|e| format!("Failed to read prompt fragment {}: {}", path, e))?;
+                String::from_utf8(data)
+                    .map_err(|e| format!("Prompt fragment {} is not UTF-8: {}", path, e))
+            }
+            FragmentSource::ContentRef { store_id, hash } => {
+                debug(&format!("Loading prompt fragment {} from store", hash));
+                let content_ref = ContentRef { hash: hash.clone() };
+                let data = store::get(store_id, &content_ref)
+                    .map_err(|e| format!("Failed to load prompt fragment {}: {}", hash, e))?;
+                String::from_utf8(data)
+                    .map_err(|e| format!("Prompt fragment {} is not UTF-8: {}", hash, e))
+            }
+        }
+    }
+}
+
+/// Builds the system prompt. Fragments are sorted by role, then by their
+/// `order`, with ties kept in the order they were given, and joined by
+/// blank lines.
+pub fn compose_system_prompt(config: Option<&SystemPromptConfig>) -> Result<String, String> {
+    match config {
+        None => Ok(DEFAULT_SYSTEM_PROMPT.to_string()),
+        Some(SystemPromptConfig::Text(text)) => Ok(text.clone()),
+        Some(SystemPromptConfig::Fragments(fragments)) => {
+            let mut ordered: Vec<&PromptFragment> = fragments.iter().collect();
+            ordered.sort_by_key(|fragment| (fragment.role, fragment.order));
+
+            let parts = ordered
+                .into_iter()
+                .map(|fragment| fragment.source.load())
+                .collect::<Result<Vec<String>, String>>()?;
+
+            Ok(parts
+                .iter()
+                .map(|part| part.trim())
+                .filter(|part| !part.is_empty())
+                .collect::<Vec<&str>>()
+                .join("\n\n"))
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use crate::secrets::ConfigString;
 use genai_types::Message;
 use mcp_protocol::tool::Tool;
 use serde::{Deserialize, Serialize};
@@ -32,6 +33,9 @@ pub struct McpError {
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct McpServer {
+    /// Used to refer to the server when adding or removing it mid-task
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
     pub actor_id: Option<String>,
     #[serde(flatten)]
     pub config: McpConfig,
@@ -41,7 +45,14 @@ pub struct McpServer {
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct StdPipeMcpConfig {
     pub command: String,
-    pub args: Vec<String>,
+    pub args: Vec<ConfigString>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub env: HashMap<String, ConfigString>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    /// Milliseconds to wait for the server to start
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub startup_timeout: Option<u64>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -66,6 +77,8 @@ pub enum ChatStateRequest {
     AddMessage { message: Message },
     #[serde(rename = "generate_completion")]
     GenerateCompletion,
+    #[serde(rename = "get_history")]
+    GetHistory,
 }
 
 /// Data associated with the response
@@ -75,6 +88,9 @@ pub enum ChatStateResponse {
     #[serde(rename = "success")]
     Success,
 
+    #[serde(rename = "history")]
+    History { messages: Vec<Message> },
+
     #[serde(rename = "error")]
     Error { error: ErrorInfo },
 }
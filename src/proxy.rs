@@ -0,0 +1,427 @@
+use crate::bindings::theater::simple::message_server_host::request;
+use crate::bindings::theater::simple::timing::now;
+use crate::cache::{CacheStats, ToolCache, ToolCacheConfig};
+use crate::limits::{CallCounts, ToolLimits, ToolUsage};
+use crate::logging::{debug, info, warn};
+use crate::mcp::{self, SharedMcpServer};
+use crate::protocol::{
+    ActorMcpConfig, McpActorRequest, McpConfig, McpError, McpResponse, McpServer,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, from_slice, to_vec};
+use std::collections::HashMap;
+
+// JSON-RPC error codes used for failures the proxy reports itself
+const METHOD_NOT_FOUND: i32 = -32601;
+const INTERNAL_ERROR: i32 = -32603;
+// Server-defined, following the codes for calls over the tool limits
+pub const CALL_REJECTED: i32 = -32003;
+
+/// The MCP actors the task manager sits in front of, which of them
+/// provides each tool, and how many calls each has taken
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct McpProxy {
+    pub servers: Vec<SharedMcpServer>,
+    /// Tool name to index in `servers`
+    routes: HashMap<String, usize>,
+    limits: ToolLimits,
+    counts: CallCounts,
+    cache: Option<ToolCache>,
+    /// Hold every tool call until it is approved or rejected
+    require_approval: bool,
+    pending: Vec<PendingCall>,
+}
+
+/// A tool call waiting for approval. Chat-state is still waiting on the
+/// request, which is answered once a decision is made.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PendingCall {
+    pub request_id: String,
+    pub tool: String,
+    pub args: Value,
+    /// Milliseconds since the epoch when chat-state made the call
+    pub requested_at: u64,
+}
+
+/// One proxied tool call
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AuditEntry {
+    pub tool: String,
+    pub server: String,
+    pub args: Value,
+    pub result: Option<Value>,
+    pub error: Option<McpError>,
+    /// Answered from the tool cache without calling the server
+    #[serde(default)]
+    pub cached: bool,
+    /// Milliseconds since the epoch when the call was made
+    pub timestamp: u64,
+    pub latency_ms: u64,
+}
+
+impl McpProxy {
+    /// Starts the actor-based servers from `mcp_servers` and puts them, along
+    /// with the shared pool, behind the proxy. Stdio servers are left to
+    /// chat-state, since only actors can be reached through the message server.
+    pub fn start(
+        mcp_servers: &[McpServer],
+        mcp_pool: &[SharedMcpServer],
+        limits: ToolLimits,
+        cache: Option<ToolCacheConfig>,
+        require_approval: bool,
+    ) -> Result<Self, String> {
+        let actor_servers: Vec<SharedMcpServer> = mcp_servers
+            .iter()
+            .enumerate()
+            .filter_map(|(index, server)| {
+                let name = server
+                    .name
+                    .clone()
+                    .unwrap_or_else(|| format!("mcp-{}", index));
+                SharedMcpServer::from_mcp_server(name, server)
+            })
+            .collect();
+
+        let mut servers = mcp::start_shared_servers(&actor_servers)?;
+        servers.extend(mcp_pool.iter().cloned());
+        info(&format!("Proxying {} MCP servers", servers.len()));
+
+        Ok(Self {
+            servers,
+            routes: HashMap::new(),
+            limits,
+            counts: CallCounts::default(),
+            cache: cache.map(ToolCache::new),
+            require_approval,
+            pending: vec![],
+        })
+    }
+
+    /// The entry chat-state uses to reach every proxied server through us
+    pub fn chat_state_server(self_id: &str) -> McpServer {
+        McpServer {
+            name: None,
+            actor_id: Some(self_id.to_string()),
+            config: McpConfig::Actor(ActorMcpConfig {
+                manifest_path: String::new(),
+                init_state: None,
+            }),
+            tools: None,
+        }
+    }
+
+    pub fn has_server(&self, name: &str) -> bool {
+        self.servers.iter().any(|server| server.name == name)
+    }
+
+    /// Puts another server behind the proxy; its tools are picked up on the next listing
+    pub fn add_server(&mut self, server: SharedMcpServer) {
+        self.servers.push(server);
+        self.routes.clear();
+    }
+
+    pub fn remove_server(&mut self, name: &str) -> Option<SharedMcpServer> {
+        let index = self.servers.iter().position(|server| server.name == name)?;
+        self.routes.clear();
+        Some(self.servers.remove(index))
+    }
+
+    /// Answers a request from chat-state, recording tool calls in the audit
+    /// log. Calls that need approval are held and `None` is returned; they
+    /// are answered through `approve` or `reject`.
+    pub fn handle(
+        &mut self,
+        request_id: &str,
+        mcp_request: McpActorRequest,
+        audit_log: &mut Vec<AuditEntry>,
+    ) -> Option<McpResponse> {
+        match mcp_request {
+            McpActorRequest::ToolsList {} => {
+                let tools = self.list_tools();
+                Some(success(request_id, serde_json::json!({ "tools": tools })))
+            }
+            McpActorRequest::ToolsCall { name, args } if self.require_approval => {
+                if self.route(&name).is_none() {
+                    return Some(unknown_tool(request_id, &name));
+                }
+                info(&format!("Holding call to {} for approval", name));
+                self.pending.push(PendingCall {
+                    request_id: request_id.to_string(),
+                    tool: name,
+                    args,
+                    requested_at: now(),
+                });
+                None
+            }
+            McpActorRequest::ToolsCall { name, args } => {
+                Some(self.call_tool(request_id, name, args, audit_log))
+            }
+        }
+    }
+
+    pub fn pending_calls(&self) -> &[PendingCall] {
+        &self.pending
+    }
+
+    /// Makes a held call and returns the response for chat-state
+    pub fn approve(
+        &mut self,
+        request_id: &str,
+        audit_log: &mut Vec<AuditEntry>,
+    ) -> Result<McpResponse, String> {
+        let call = self.take_pending(request_id)?;
+        info(&format!("Call to {} approved", call.tool));
+        Ok(self.call_tool(request_id, call.tool, call.args, audit_log))
+    }
+
+    /// Drops a held call and returns the error for chat-state
+    pub fn reject(
+        &mut self,
+        request_id: &str,
+        reason: Option<String>,
+        audit_log: &mut Vec<AuditEntry>,
+    ) -> Result<McpResponse, String> {
+        let call = self.take_pending(request_id)?;
+        info(&format!("Call to {} rejected", call.tool));
+        let error = McpError {
+            code: CALL_REJECTED,
+            message: reason.unwrap_or_else(|| format!("Call to {} was rejected", call.tool)),
+            data: None,
+        };
+        let server = self
+            .route(&call.tool)
+            .and_then(|server| server.actor_id.clone())
+            .unwrap_or_default();
+        audit_log.push(AuditEntry {
+            tool: call.tool,
+            server,
+            args: call.args,
+            result: None,
+            error: Some(error.clone()),
+            cached: false,
+            timestamp: now(),
+            latency_ms: 0,
+        });
+        Ok(error_response(request_id, error))
+    }
+
+    fn take_pending(&mut self, request_id: &str) -> Result<PendingCall, String> {
+        let index = self
+            .pending
+            .iter()
+            .position(|call| call.request_id == request_id)
+            .ok_or_else(|| format!("No tool call {} is waiting for approval", request_id))?;
+        Ok(self.pending.remove(index))
+    }
+
+    /// The server providing a tool, listing tools first if it isn't known yet
+    fn route(&mut self, tool: &str) -> Option<&SharedMcpServer> {
+        if !self.routes.contains_key(tool) {
+            self.list_tools();
+        }
+        self.routes
+            .get(tool)
+            .and_then(|index| self.servers.get(*index))
+    }
+
+    fn call_tool(
+        &mut self,
+        request_id: &str,
+        name: String,
+        args: Value,
+        audit_log: &mut Vec<AuditEntry>,
+    ) -> McpResponse {
+        let Some(server) = self.route(&name) else {
+            return unknown_tool(request_id, &name);
+        };
+
+        let server_name = server.name.clone();
+        let actor_id = server.actor_id.clone().unwrap_or_default();
+        let timestamp = now();
+
+        // Cache hits don't reach the server, so they don't count against its limits
+        if let Some(cache) = &mut self.cache
+            && let Some(mut response) = cache.lookup(&server_name, &name, &args, timestamp)
+        {
+            debug(&format!("Answering {} from the tool cache", name));
+            response.id = request_id.to_string();
+            audit_log.push(AuditEntry {
+                tool: name,
+                server: actor_id,
+                args,
+                result: response.result.clone(),
+                error: None,
+                cached: true,
+                timestamp,
+                latency_ms: 0,
+            });
+            return response;
+        }
+
+        if let Err(error) = self
+            .counts
+            .admit(&self.limits, &server_name, &name, timestamp)
+        {
+            warn(&format!("Refusing call to {}: {}", name, error.message));
+            audit_log.push(AuditEntry {
+                tool: name,
+                server: actor_id,
+                args,
+                result: None,
+                error: Some(error.clone()),
+                cached: false,
+                timestamp,
+                latency_ms: 0,
+            });
+            return error_response(request_id, error);
+        }
+
+        let response = call_server(
+            &actor_id,
+            &McpActorRequest::ToolsCall {
+                name: name.clone(),
+                args: args.clone(),
+            },
+        )
+        .unwrap_or_else(|e| {
+            warn(&format!("Proxied call to {} failed: {}", name, e));
+            failure(request_id, INTERNAL_ERROR, e)
+        });
+        let latency_ms = now().saturating_sub(timestamp);
+
+        debug(&format!(
+            "Tool {} on {} finished in {}ms",
+            name, actor_id, latency_ms
+        ));
+        if let Some(cache) = &mut self.cache {
+            cache.insert(&server_name, &name, &args, &response, timestamp);
+        }
+        audit_log.push(AuditEntry {
+            tool: name,
+            server: actor_id,
+            args,
+            result: response.result.clone(),
+            error: response.error.clone(),
+            cached: false,
+            timestamp,
+            latency_ms,
+        });
+        response
+    }
+
+    /// Calls made so far against each server and tool
+    pub fn usage(&self) -> ToolUsage {
+        self.counts.usage(now())
+    }
+
+    pub fn cache_stats(&self) -> Option<CacheStats> {
+        self.cache.as_ref().map(ToolCache::stats)
+    }
+
+    /// Collects the tools of every proxied server and refreshes the routes.
+    /// Servers that fail to answer are logged and left out.
+    fn list_tools(&mut self) -> Vec<Value> {
+        let mut tools = vec![];
+        self.routes.clear();
+
+        for (index, server) in self.servers.iter().enumerate() {
+            let Some(actor_id) = &server.actor_id else {
+                continue;
+            };
+
+            let server_tools = match &server.tools {
+                Some(configured) => configured
+                    .iter()
+                    .filter_map(|tool| serde_json::to_value(tool).ok())
+                    .collect(),
+                None => match list_server_tools(actor_id) {
+                    Ok(server_tools) => server_tools,
+                    Err(e) => {
+                        warn(&format!(
+                            "Failed to list tools of MCP server {}: {}",
+                            server.name, e
+                        ));
+                        continue;
+                    }
+                },
+            };
+
+            for tool in server_tools {
+                if let Some(name) = tool.get("name").and_then(Value::as_str) {
+                    if self.routes.contains_key(name) {
+                        warn(&format!(
+                            "Tool {} is provided by several servers, using the first",
+                            name
+                        ));
+                        continue;
+                    }
+                    self.routes.insert(name.to_string(), index);
+                    tools.push(tool);
+                }
+            }
+        }
+
+        tools
+    }
+}
+
+fn list_server_tools(actor_id: &str) -> Result<Vec<Value>, String> {
+    let response = call_server(actor_id, &McpActorRequest::ToolsList {})?;
+    if let Some(error) = response.error {
+        return Err(format!("error {}: {}", error.code, error.message));
+    }
+
+    Ok(response
+        .result
+        .as_ref()
+        .and_then(|result| result.get("tools"))
+        .and_then(Value::as_array)
+        .cloned()
+        .unwrap_or_default())
+}
+
+fn call_server(actor_id: &str, mcp_request: &McpActorRequest) -> Result<McpResponse, String> {
+    let request_data =
+        to_vec(mcp_request).map_err(|e| format!("Failed to serialize MCP request: {}", e))?;
+    let response_data = request(actor_id, &request_data)
+        .map_err(|e| format!("MCP request to {} failed: {}", actor_id, e))?;
+    from_slice(&response_data).map_err(|e| format!("Failed to parse MCP response: {}", e))
+}
+
+fn unknown_tool(request_id: &str, name: &str) -> McpResponse {
+    warn(&format!("No proxied MCP server provides tool {}", name));
+    failure(
+        request_id,
+        METHOD_NOT_FOUND,
+        format!("Unknown tool: {}", name),
+    )
+}
+
+fn success(request_id: &str, result: Value) -> McpResponse {
+    McpResponse {
+        jsonrpc: "2.0".to_string(),
+        id: request_id.to_string(),
+        result: Some(result),
+        error: None,
+    }
+}
+
+fn failure(request_id: &str, code: i32, message: String) -> McpResponse {
+    error_response(
+        request_id,
+        McpError {
+            code,
+            message,
+            data: None,
+        },
+    )
+}
+
+fn error_response(request_id: &str, error: McpError) -> McpResponse {
+    McpResponse {
+        jsonrpc: "2.0".to_string(),
+        id: request_id.to_string(),
+        result: None,
+        error: Some(error),
+    }
+}
@@ -0,0 +1,322 @@
+use crate::bindings::theater::simple::message_server_host::request;
+use crate::bindings::theater::simple::supervisor::{spawn, stop_child};
+use crate::logging::{info, warn};
+use crate::mcp::SharedMcpServer;
+use crate::metadata::TaskMetadata;
+use crate::models::{self, ModelPresets};
+use crate::{TASK_MANAGER_MANIFEST_PATH, TaskManagerRequest, TaskStatus, fork, verifier};
+use genai_types::Message;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, to_vec};
+
+const DEFAULT_JUDGE_PROMPT: &str = "You are judging the results several AI assistants produced for the same task. Pick the one that best and most completely accomplishes it.";
+
+const JUDGE_INSTRUCTION: &str = "Respond with only a JSON object of the form {\"winner\": <candidate number>, \"reason\": \"...\"}.";
+
+/// Runs the task on several models at once, each in its own task manager
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RaceConfig {
+    /// Preset names or proxy configs, one racer each
+    pub models: Vec<Value>,
+    #[serde(default)]
+    pub pick: RacePick,
+    /// A proxy config or preset name; defaults to the task's own model
+    pub judge_model: Option<Value>,
+    pub judge_prompt: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RacePick {
+    /// The first racer to complete wins
+    #[default]
+    First,
+    /// Every racer runs to the end, then a judge picks among the results
+    Best,
+}
+
+/// The race as reported by `GetStatus`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RaceState {
+    pub config: RaceConfig,
+    pub racers: Vec<Racer>,
+    /// Index in `racers`
+    pub winner: Option<usize>,
+    /// The judge's explanation, when the best result was picked
+    pub reason: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Racer {
+    pub actor_id: String,
+    pub model: String,
+    /// Set once the racer finished, completed or not
+    pub status: Option<TaskStatus>,
+    pub result: Option<Value>,
+}
+
+/// What the race is waiting for after a racer finished
+pub enum Outcome {
+    Running,
+    Won(usize),
+    /// Everyone is done and the judge has to pick among the completed
+    Judge(Vec<usize>),
+    Lost,
+}
+
+impl RaceState {
+    pub fn new(config: RaceConfig) -> Self {
+        Self {
+            config,
+            racers: vec![],
+            winner: None,
+            reason: None,
+        }
+    }
+
+    pub fn started(&self) -> bool {
+        !self.racers.is_empty()
+    }
+
+    /// Spawns one task manager per model, each a copy of this task that
+    /// reports back to us when it finishes, and starts them
+    pub fn start(
+        &mut self,
+        self_id: &str,
+        task_config: &Value,
+        metadata: &TaskMetadata,
+        mcp_pool: &[SharedMcpServer],
+        history: &[Message],
+    ) -> Result<(), String> {
+        if self.config.models.is_empty() {
+            return Err("A race needs at least one model".to_string());
+        }
+
+        for model in &self.config.models {
+            // A preset name replaces our preset, a proxy config our proxy
+            let model_key = if model.is_string() {
+                "model"
+            } else {
+                "model_proxy"
+            };
+            let actor_id = spawn_copy(
+                self_id,
+                task_config,
+                metadata,
+                mcp_pool,
+                history,
+                serde_json::json!({ model_key: model }),
+            )?;
+            let model = model_name(model);
+            info(&format!("Racer {} started on {}", actor_id, model));
+            self.racers.push(Racer {
+                actor_id,
+                model,
+                status: None,
+                result: None,
+            });
+        }
+
+        start_all(self.racers.iter().map(|racer| racer.actor_id.as_str()));
+        Ok(())
+    }
+
+    /// Records how a racer finished. Returns `None` if the actor isn't one of
+    /// our racers, has already finished, or the race is already decided.
+    pub fn record(
+        &mut self,
+        actor_id: &str,
+        status: TaskStatus,
+        result: Option<Value>,
+    ) -> Option<Outcome> {
+        if self.winner.is_some() {
+            return None;
+        }
+        let index = self
+            .racers
+            .iter()
+            .position(|racer| racer.actor_id == actor_id && racer.status.is_none())?;
+        let racer = &mut self.racers[index];
+        info(&format!(
+            "Racer {} on {} finished: {:?}",
+            racer.actor_id, racer.model, status
+        ));
+        racer.status = Some(status);
+        racer.result = result;
+
+        let completed: Vec<usize> = self
+            .racers
+            .iter()
+            .enumerate()
+            .filter(|(_, racer)| racer.status == Some(TaskStatus::Completed))
+            .map(|(index, _)| index)
+            .collect();
+
+        if status == TaskStatus::Completed && self.config.pick == RacePick::First {
+            return Some(Outcome::Won(index));
+        }
+        if self.racers.iter().any(|racer| racer.status.is_none()) {
+            return Some(Outcome::Running);
+        }
+        Some(match completed.as_slice() {
+            [] => Outcome::Lost,
+            [only] => Outcome::Won(*only),
+            _ => Outcome::Judge(completed),
+        })
+    }
+
+    /// Has the judge pick among the completed racers. If the judge can't
+    /// decide, the first of them wins.
+    pub fn judge(
+        &mut self,
+        candidates: &[usize],
+        task: Option<&str>,
+        current_model_proxy: &Value,
+        presets: &ModelPresets,
+    ) -> usize {
+        match self.ask_judge(candidates, task, current_model_proxy, presets) {
+            Ok((winner, reason)) => {
+                self.reason = reason;
+                winner
+            }
+            Err(e) => {
+                warn(&format!("Judge failed, taking the first result: {}", e));
+                candidates[0]
+            }
+        }
+    }
+
+    fn ask_judge(
+        &self,
+        candidates: &[usize],
+        task: Option<&str>,
+        current_model_proxy: &Value,
+        presets: &ModelPresets,
+    ) -> Result<(usize, Option<String>), String> {
+        let model_proxy = match &self.config.judge_model {
+            Some(Value::String(name)) => models::get_preset(presets, name)?.model_proxy(),
+            Some(proxy) => proxy.clone(),
+            None => current_model_proxy.clone(),
+        };
+        info(&format!(
+            "Judging {} results with {}",
+            candidates.len(),
+            models::model_label(&model_proxy)
+        ));
+
+        let rendered: Vec<String> = candidates
+            .iter()
+            .enumerate()
+            .map(|(number, index)| {
+                let result = self.racers[*index]
+                    .result
+                    .as_ref()
+                    .map(Value::to_string)
+                    .unwrap_or_else(|| "(no result)".to_string());
+                format!("Candidate {}:\n{}", number + 1, result)
+            })
+            .collect();
+        let question = format!(
+            "Task:\n{}\n\n{}",
+            task.unwrap_or("(not given)"),
+            rendered.join("\n\n")
+        );
+        let system_prompt = format!(
+            "{}\n\n{}",
+            self.config
+                .judge_prompt
+                .as_deref()
+                .unwrap_or(DEFAULT_JUDGE_PROMPT),
+            JUDGE_INSTRUCTION
+        );
+
+        let reply = verifier::ask_model(&model_proxy, "Race judging", &system_prompt, question)?;
+        let decision: JudgeReply = verifier::parse_json_reply(&reply, "judgement")?;
+        let winner = decision
+            .winner
+            .checked_sub(1)
+            .and_then(|number| candidates.get(number))
+            .ok_or_else(|| format!("Judge picked unknown candidate {}", decision.winner))?;
+        Ok((*winner, decision.reason))
+    }
+
+    /// Stops every racer still running
+    pub fn stop_racers(&self) {
+        stop_all(self.racers.iter().map(|racer| racer.actor_id.as_str()));
+    }
+}
+
+/// Spawns a copy of this task as our child, starting from `history`, that
+/// notifies us when it finishes instead of exiting. `overrides` replace keys
+/// of our config as they do for a fork.
+pub fn spawn_copy(
+    self_id: &str,
+    task_config: &Value,
+    metadata: &TaskMetadata,
+    mcp_pool: &[SharedMcpServer],
+    history: &[Message],
+    mut overrides: Value,
+) -> Result<String, String> {
+    overrides["initial_message"] = task_config
+        .get("initial_message")
+        .cloned()
+        .unwrap_or_default();
+    overrides["notify"] = serde_json::json!([self_id]);
+    overrides["auto_exit_on_completion"] = Value::Bool(false);
+    let mut config = fork::fork_config(
+        task_config,
+        metadata,
+        mcp_pool,
+        history,
+        history.len(),
+        Some(&overrides),
+    )?;
+    if let Some(config) = config.as_object_mut() {
+        config.remove("race");
+        config.remove("samples");
+        config.remove("sample_temperature");
+    }
+
+    let config_bytes =
+        to_vec(&config).map_err(|e| format!("Failed to serialize task config: {}", e))?;
+    spawn(TASK_MANAGER_MANIFEST_PATH, Some(&config_bytes))
+        .map_err(|e| format!("Failed to spawn task manager: {}", e))
+}
+
+/// Sends `StartChat` to each of the given task managers
+pub fn start_all<'a>(actor_ids: impl Iterator<Item = &'a str>) {
+    let start_data = match to_vec(&TaskManagerRequest::StartChat) {
+        Ok(data) => data,
+        Err(e) => {
+            warn(&format!("Failed to serialize StartChat: {}", e));
+            return;
+        }
+    };
+    for actor_id in actor_ids {
+        if let Err(e) = request(actor_id, &start_data) {
+            warn(&format!("Failed to start {}: {}", actor_id, e));
+        }
+    }
+}
+
+/// Stops each of the given task managers
+pub fn stop_all<'a>(actor_ids: impl Iterator<Item = &'a str>) {
+    for actor_id in actor_ids {
+        if let Err(e) = stop_child(actor_id) {
+            warn(&format!("Failed to stop {}: {}", actor_id, e));
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct JudgeReply {
+    winner: usize,
+    reason: Option<String>,
+}
+
+fn model_name(model: &Value) -> String {
+    match model {
+        Value::String(preset) => preset.clone(),
+        proxy => models::model_label(proxy),
+    }
+}
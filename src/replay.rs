@@ -0,0 +1,297 @@
+use crate::logging::info;
+use crate::protocol::ChatStateResponse;
+use crate::{TaskComplete, TaskManagerRequest};
+use genai_types::{Message, MessageContent, messages::Role};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, from_slice};
+use std::collections::VecDeque;
+
+/// Event types of messages delivered to us go through our message server
+/// client export; everything else, such as requests we sent, is outbound
+const INBOUND_EVENT_MARKER: &str = "message-server-client";
+
+/// A recorded event chain to replay, as returned by `runtime::get_chain`
+/// or `supervisor::get_child_events`. Events from several chains (ours and
+/// chat-state's) can be concatenated; they are used in the order given.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReplayConfig {
+    pub events: Vec<RecordedEvent>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RecordedEvent {
+    pub event_type: String,
+    pub data: Vec<u8>,
+}
+
+/// Something the task manager received in the recorded run
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ReplayInput {
+    Request(TaskManagerRequest),
+    TaskComplete(TaskComplete),
+}
+
+/// What is left to replay
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReplayState {
+    inputs: VecDeque<ReplayInput>,
+    /// The final transcript of the recorded run, which model and tool
+    /// output is taken from instead of calling the model
+    transcript: Vec<Message>,
+}
+
+impl ReplayState {
+    /// Rebuilds the inputs and transcript of the recorded run. Event data
+    /// is searched for embedded message payloads, which are recognized by
+    /// their content; the same payload logged twice in a row (on receipt
+    /// and on handling) is only used once. Only messages delivered to us
+    /// become inputs, and of requests only those without side effects
+    /// outside the task.
+    pub fn from_events(events: &[RecordedEvent]) -> Self {
+        let state = Self::from_payloads(&extract_payloads(events));
+        info(&format!(
+            "Replay rebuilt {} inputs and a transcript of {} messages from {} events",
+            state.inputs.len(),
+            state.transcript.len(),
+            events.len()
+        ));
+        state
+    }
+
+    /// Sorts `(inbound, payload)` pairs into inputs and the transcript
+    fn from_payloads(payloads: &[(bool, Vec<u8>)]) -> Self {
+        let mut inputs = VecDeque::new();
+        let mut transcript = vec![];
+        for (inbound, payload) in payloads {
+            if *inbound && let Ok(request) = from_slice::<TaskManagerRequest>(payload) {
+                if is_replayable(&request) {
+                    inputs.push_back(ReplayInput::Request(request));
+                }
+            } else if *inbound && let Some(completion) = TaskComplete::parse(payload) {
+                inputs.push_back(ReplayInput::TaskComplete(completion));
+            } else if let Ok(ChatStateResponse::History { messages }) = from_slice(payload)
+                && messages.len() > transcript.len()
+            {
+                transcript = messages;
+            }
+        }
+        Self { inputs, transcript }
+    }
+
+    pub fn next_input(&mut self) -> Option<ReplayInput> {
+        self.inputs.pop_front()
+    }
+
+    /// The recorded model turn following a transcript of `current_len`
+    /// messages: everything up to the next message a user typed, including
+    /// tool calls and their results
+    pub fn next_turn(&self, current_len: usize) -> &[Message] {
+        let remaining = self.transcript.get(current_len..).unwrap_or_default();
+        let end = remaining
+            .iter()
+            .position(is_user_text)
+            .unwrap_or(remaining.len());
+        &remaining[..end]
+    }
+}
+
+/// Requests that only feed the conversation. The rest would spawn actors,
+/// write to the store or stop the task for real.
+fn is_replayable(request: &TaskManagerRequest) -> bool {
+    matches!(
+        request,
+        TaskManagerRequest::StartChat
+            | TaskManagerRequest::AddMessage { .. }
+            | TaskManagerRequest::Reopen { .. }
+    )
+}
+
+fn is_user_text(message: &Message) -> bool {
+    matches!(message.role, Role::User)
+        && message
+            .content
+            .iter()
+            .any(|content| matches!(content, MessageContent::Text { .. }))
+}
+
+/// The message payloads in the events, each marked with whether it was
+/// delivered to us. Data that isn't JSON is taken as a payload itself.
+fn extract_payloads(events: &[RecordedEvent]) -> Vec<(bool, Vec<u8>)> {
+    let mut payloads: Vec<(bool, Vec<u8>)> = vec![];
+    for event in events {
+        let inbound = event.event_type.contains(INBOUND_EVENT_MARKER);
+        let mut found = vec![];
+        match from_slice::<Value>(&event.data) {
+            Ok(data) => collect_payloads(&data, &mut found),
+            Err(_) => found.push(event.data.clone()),
+        }
+        for payload in found {
+            let payload = (inbound, payload);
+            if payloads.last() != Some(&payload) {
+                payloads.push(payload);
+            }
+        }
+    }
+    payloads
+}
+
+/// Finds byte arrays in event data, the form message payloads are logged in
+fn collect_payloads(value: &Value, payloads: &mut Vec<Vec<u8>>) {
+    match value {
+        Value::Array(items) => {
+            let bytes: Option<Vec<u8>> = items
+                .iter()
+                .map(|item| item.as_u64().and_then(|byte| u8::try_from(byte).ok()))
+                .collect();
+            match bytes {
+                Some(bytes) if !bytes.is_empty() => payloads.push(bytes),
+                _ => {
+                    for item in items {
+                        collect_payloads(item, payloads);
+                    }
+                }
+            }
+        }
+        Value::Object(object) => {
+            for item in object.values() {
+                collect_payloads(item, payloads);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, to_vec};
+
+    fn event(event_type: &str, data: Value) -> RecordedEvent {
+        RecordedEvent {
+            event_type: event_type.to_string(),
+            data: to_vec(&data).unwrap(),
+        }
+    }
+
+    fn bytes(payload: Value) -> Value {
+        json!(to_vec(&payload).unwrap())
+    }
+
+    fn text(role: Role, text: &str) -> Message {
+        Message {
+            role,
+            content: vec![MessageContent::Text {
+                text: text.to_string(),
+            }],
+        }
+    }
+
+    #[test]
+    fn collects_nested_byte_arrays() {
+        let data = json!({
+            "request": { "data": [1, 2, 3] },
+            "items": [[4, 5], "text", [256, 1], []],
+            "count": 7
+        });
+        let mut payloads = vec![];
+        collect_payloads(&data, &mut payloads);
+        payloads.sort();
+        assert_eq!(payloads, vec![vec![1, 2, 3], vec![4, 5]]);
+    }
+
+    #[test]
+    fn marks_direction_and_drops_repeats() {
+        let start = bytes(json!({ "type": "StartChat" }));
+        let events = vec![
+            event(
+                "theater:simple/message-server-client/handle-request",
+                json!({ "data": start }),
+            ),
+            event(
+                "theater:simple/message-server-client/handle-request",
+                json!({ "data": start }),
+            ),
+            event(
+                "theater:simple/message-server-host/request",
+                json!({ "data": start }),
+            ),
+            RecordedEvent {
+                event_type: "theater:simple/message-server-client/handle-send".to_string(),
+                data: b"null".to_vec(),
+            },
+            RecordedEvent {
+                event_type: "theater:simple/message-server-client/handle-send".to_string(),
+                data: vec![0xff, 0x00],
+            },
+        ];
+        let payloads = extract_payloads(&events);
+        let start = to_vec(&json!({ "type": "StartChat" })).unwrap();
+        assert_eq!(
+            payloads,
+            vec![
+                (true, start.clone()),
+                (false, start),
+                (true, vec![0xff, 0x00])
+            ]
+        );
+    }
+
+    #[test]
+    fn only_inbound_side_effect_free_requests_become_inputs() {
+        let payload = |value: Value| to_vec(&value).unwrap();
+        let state = ReplayState::from_payloads(&[
+            (true, payload(json!({ "type": "StartChat" }))),
+            // Sent by us to a fork or racer
+            (false, payload(json!({ "type": "StartChat" }))),
+            (true, payload(json!({ "type": "Cancel" }))),
+            (true, payload(json!({ "type": "GetStatus" }))),
+            (true, payload(json!({ "result": { "answer": 42 } }))),
+            (false, payload(json!({ "result": { "answer": 0 } }))),
+        ]);
+        let inputs: Vec<ReplayInput> = state.inputs.into_iter().collect();
+        assert_eq!(inputs.len(), 2);
+        assert!(matches!(
+            inputs[0],
+            ReplayInput::Request(TaskManagerRequest::StartChat)
+        ));
+        assert!(matches!(
+            &inputs[1],
+            ReplayInput::TaskComplete(TaskComplete { result: Some(result) })
+                if result == &json!({ "answer": 42 })
+        ));
+    }
+
+    #[test]
+    fn keeps_the_longest_history_as_the_transcript() {
+        let history =
+            |messages: Vec<Message>| to_vec(&ChatStateResponse::History { messages }).unwrap();
+        let question = text(Role::User, "What is 6 * 7?");
+        let answer = text(Role::Assistant, "42");
+        let state = ReplayState::from_payloads(&[
+            (false, history(vec![question.clone()])),
+            (false, history(vec![question.clone(), answer.clone()])),
+            (false, history(vec![question])),
+        ]);
+        assert_eq!(state.transcript.len(), 2);
+        assert!(state.inputs.is_empty());
+    }
+
+    #[test]
+    fn a_turn_runs_up_to_the_next_user_text() {
+        let state = ReplayState {
+            inputs: VecDeque::new(),
+            transcript: vec![
+                text(Role::User, "Start"),
+                text(Role::Assistant, "Working"),
+                text(Role::Assistant, "Done"),
+                text(Role::User, "Again"),
+                text(Role::Assistant, "Done again"),
+            ],
+        };
+        assert_eq!(state.next_turn(1).len(), 2);
+        assert_eq!(state.next_turn(4).len(), 1);
+        assert!(state.next_turn(5).is_empty());
+        assert!(state.next_turn(9).is_empty());
+    }
+}
@@ -0,0 +1,278 @@
+use crate::TaskStatus;
+use crate::bindings::theater::simple::random::random_float;
+use crate::logging::{info, warn};
+use crate::mcp::SharedMcpServer;
+use crate::metadata::TaskMetadata;
+use crate::race;
+use genai_types::Message;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+const DEFAULT_MIN_TEMPERATURE: f64 = 0.5;
+const DEFAULT_MAX_TEMPERATURE: f64 = 1.0;
+
+/// Each sample runs at a temperature drawn uniformly from this range
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct TemperatureRange {
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Default for TemperatureRange {
+    fn default() -> Self {
+        Self {
+            min: DEFAULT_MIN_TEMPERATURE,
+            max: DEFAULT_MAX_TEMPERATURE,
+        }
+    }
+}
+
+impl TemperatureRange {
+    fn draw(&self) -> f64 {
+        match random_float() {
+            Ok(unit) => self.min + (self.max - self.min) * unit,
+            Err(e) => {
+                warn(&format!(
+                    "Failed to draw a sample temperature, using {}: {}",
+                    self.min, e
+                ));
+                self.min
+            }
+        }
+    }
+}
+
+/// The runs of a task sampled several times, as reported by `GetStatus`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SampleState {
+    pub count: u32,
+    pub temperature: TemperatureRange,
+    pub samples: Vec<Sample>,
+    pub vote: Option<Vote>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Sample {
+    pub actor_id: String,
+    pub temperature: f64,
+    /// Set once the sample finished, completed or not
+    pub status: Option<TaskStatus>,
+    pub result: Option<Value>,
+}
+
+/// The majority answer among the completed samples
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Vote {
+    pub answer: Option<Value>,
+    /// Samples that gave the answer
+    pub votes: usize,
+    pub completed: usize,
+    pub samples: usize,
+    /// `votes` over `samples`, so runs that didn't complete count against it
+    pub agreement: f64,
+    /// Index in `samples` of the first run that gave the answer
+    pub sample: usize,
+}
+
+impl SampleState {
+    pub fn new(count: u32, temperature: Option<TemperatureRange>) -> Self {
+        Self {
+            count,
+            temperature: temperature.unwrap_or_default(),
+            samples: vec![],
+            vote: None,
+        }
+    }
+
+    pub fn started(&self) -> bool {
+        !self.samples.is_empty()
+    }
+
+    /// Spawns one task manager per sample, each a copy of this task at its
+    /// own temperature, and starts them
+    pub fn start(
+        &mut self,
+        self_id: &str,
+        task_config: &Value,
+        metadata: &TaskMetadata,
+        mcp_pool: &[SharedMcpServer],
+        history: &[Message],
+    ) -> Result<(), String> {
+        if self.count == 0 {
+            return Err("samples must be at least 1".to_string());
+        }
+        if self.temperature.min > self.temperature.max {
+            return Err(format!(
+                "Invalid sample temperature range {}..{}",
+                self.temperature.min, self.temperature.max
+            ));
+        }
+
+        for _ in 0..self.count {
+            let temperature = self.temperature.draw();
+            let actor_id = race::spawn_copy(
+                self_id,
+                task_config,
+                metadata,
+                mcp_pool,
+                history,
+                serde_json::json!({ "temperature": temperature }),
+            )?;
+            info(&format!(
+                "Sample {} started at temperature {:.2}",
+                actor_id, temperature
+            ));
+            self.samples.push(Sample {
+                actor_id,
+                temperature,
+                status: None,
+                result: None,
+            });
+        }
+
+        race::start_all(self.samples.iter().map(|sample| sample.actor_id.as_str()));
+        Ok(())
+    }
+
+    /// Records how a sample finished, and once every sample has, counts the
+    /// votes. Returns `None` if the actor isn't one of our samples or has
+    /// already finished, or while samples are still running.
+    pub fn record(
+        &mut self,
+        actor_id: &str,
+        status: TaskStatus,
+        result: Option<Value>,
+    ) -> Option<Option<Vote>> {
+        if self.vote.is_some() {
+            return None;
+        }
+        let sample = self
+            .samples
+            .iter_mut()
+            .find(|sample| sample.actor_id == actor_id && sample.status.is_none())?;
+        info(&format!(
+            "Sample {} finished: {:?}",
+            sample.actor_id, status
+        ));
+        sample.status = Some(status);
+        sample.result = result;
+
+        if self.samples.iter().any(|sample| sample.status.is_none()) {
+            return None;
+        }
+        self.vote = self.count_votes();
+        Some(self.vote.clone())
+    }
+
+    /// Groups the completed results by equal value. The largest group wins,
+    /// and a tie goes to the answer given first.
+    fn count_votes(&self) -> Option<Vote> {
+        let completed: Vec<(usize, &Option<Value>)> = self
+            .samples
+            .iter()
+            .enumerate()
+            .filter(|(_, sample)| sample.status == Some(TaskStatus::Completed))
+            .map(|(index, sample)| (index, &sample.result))
+            .collect();
+
+        let mut tally: Vec<(usize, &Option<Value>, usize)> = vec![];
+        for (index, result) in &completed {
+            match tally.iter_mut().find(|(_, answer, _)| answer == result) {
+                Some((_, _, votes)) => *votes += 1,
+                None => tally.push((*index, result, 1)),
+            }
+        }
+
+        let mut winner: Option<&(usize, &Option<Value>, usize)> = None;
+        for entry in &tally {
+            if winner.is_none_or(|best| entry.2 > best.2) {
+                winner = Some(entry);
+            }
+        }
+        let (sample, answer, votes) = winner?;
+
+        Some(Vote {
+            answer: (*answer).clone(),
+            votes: *votes,
+            completed: completed.len(),
+            samples: self.samples.len(),
+            agreement: *votes as f64 / self.samples.len() as f64,
+            sample: *sample,
+        })
+    }
+
+    /// Stops every sample still running
+    pub fn stop_samples(&self) {
+        race::stop_all(self.samples.iter().map(|sample| sample.actor_id.as_str()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn finished(results: &[(TaskStatus, Option<Value>)]) -> SampleState {
+        let mut state = SampleState::new(results.len() as u32, None);
+        state.samples = results
+            .iter()
+            .enumerate()
+            .map(|(index, (status, result))| Sample {
+                actor_id: format!("sample-{}", index),
+                temperature: 0.7,
+                status: Some(*status),
+                result: result.clone(),
+            })
+            .collect();
+        state
+    }
+
+    #[test]
+    fn majority_answer_wins() {
+        let state = finished(&[
+            (TaskStatus::Completed, Some(json!({ "answer": 41 }))),
+            (TaskStatus::Completed, Some(json!({ "answer": 42 }))),
+            (TaskStatus::Completed, Some(json!({ "answer": 42 }))),
+            (TaskStatus::Failed, None),
+        ]);
+        let vote = state.count_votes().unwrap();
+        assert_eq!(vote.answer, Some(json!({ "answer": 42 })));
+        assert_eq!(vote.votes, 2);
+        assert_eq!(vote.completed, 3);
+        assert_eq!(vote.samples, 4);
+        assert_eq!(vote.agreement, 0.5);
+        assert_eq!(vote.sample, 1);
+    }
+
+    #[test]
+    fn tie_goes_to_the_answer_given_first() {
+        let state = finished(&[
+            (TaskStatus::Completed, Some(json!("b"))),
+            (TaskStatus::Completed, Some(json!("a"))),
+            (TaskStatus::Completed, Some(json!("a"))),
+            (TaskStatus::Completed, Some(json!("b"))),
+        ]);
+        let vote = state.count_votes().unwrap();
+        assert_eq!(vote.answer, Some(json!("b")));
+        assert_eq!(vote.sample, 0);
+    }
+
+    #[test]
+    fn results_that_did_not_complete_are_not_counted() {
+        let state = finished(&[
+            (TaskStatus::Stalled, Some(json!("x"))),
+            (TaskStatus::Failed, Some(json!("x"))),
+            (TaskStatus::Completed, Some(json!("y"))),
+        ]);
+        let vote = state.count_votes().unwrap();
+        assert_eq!(vote.answer, Some(json!("y")));
+        assert_eq!(vote.votes, 1);
+        assert_eq!(vote.completed, 1);
+    }
+
+    #[test]
+    fn no_vote_without_a_completed_sample() {
+        let state = finished(&[(TaskStatus::Failed, None), (TaskStatus::Stalled, None)]);
+        assert!(state.count_votes().is_none());
+    }
+}
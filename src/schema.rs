@@ -0,0 +1,298 @@
+use serde_json::Value;
+
+/// Validates a value against a JSON Schema, returning a list of problems.
+///
+/// Supports the subset of JSON Schema that task results use in practice:
+/// `type`, `enum`, `const`, `properties`, `required`, `additionalProperties`,
+/// `items`, `minItems`/`maxItems`, `minLength`/`maxLength`,
+/// `minimum`/`maximum` and `anyOf`/`oneOf`/`allOf`. Unknown keywords are ignored.
+pub fn validate(schema: &Value, value: &Value) -> Vec<String> {
+    let mut errors = vec![];
+    validate_at(schema, value, "$", &mut errors);
+    errors
+}
+
+fn validate_at(schema: &Value, value: &Value, path: &str, errors: &mut Vec<String>) {
+    let Some(schema) = schema.as_object() else {
+        // `true` accepts anything, `false` accepts nothing
+        if schema == &Value::Bool(false) {
+            errors.push(format!("{}: no value is allowed here", path));
+        }
+        return;
+    };
+
+    if let Some(expected) = schema.get("type")
+        && !matches_type(expected, value)
+    {
+        errors.push(format!(
+            "{}: expected type {}, found {}",
+            path,
+            expected,
+            type_name(value)
+        ));
+        return;
+    }
+
+    if let Some(Value::Array(options)) = schema.get("enum")
+        && !options.contains(value)
+    {
+        errors.push(format!(
+            "{}: value must be one of {}",
+            path,
+            Value::Array(options.clone())
+        ));
+    }
+
+    if let Some(constant) = schema.get("const")
+        && constant != value
+    {
+        errors.push(format!("{}: value must be {}", path, constant));
+    }
+
+    match value {
+        Value::Object(object) => {
+            if let Some(Value::Array(required)) = schema.get("required") {
+                for key in required.iter().filter_map(Value::as_str) {
+                    if !object.contains_key(key) {
+                        errors.push(format!("{}: missing required property '{}'", path, key));
+                    }
+                }
+            }
+
+            let properties = schema.get("properties").and_then(Value::as_object);
+            for (key, property_value) in object {
+                let property_path = format!("{}.{}", path, key);
+                match properties.and_then(|properties| properties.get(key)) {
+                    Some(property_schema) => {
+                        validate_at(property_schema, property_value, &property_path, errors)
+                    }
+                    None => match schema.get("additionalProperties") {
+                        Some(Value::Bool(false)) => {
+                            errors.push(format!("{}: property is not allowed", property_path))
+                        }
+                        Some(additional) => {
+                            validate_at(additional, property_value, &property_path, errors)
+                        }
+                        None => {}
+                    },
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(min) = schema.get("minItems").and_then(Value::as_u64)
+                && (items.len() as u64) < min
+            {
+                errors.push(format!("{}: expected at least {} items", path, min));
+            }
+            if let Some(max) = schema.get("maxItems").and_then(Value::as_u64)
+                && (items.len() as u64) > max
+            {
+                errors.push(format!("{}: expected at most {} items", path, max));
+            }
+            if let Some(item_schema) = schema.get("items") {
+                for (index, item) in items.iter().enumerate() {
+                    validate_at(item_schema, item, &format!("{}[{}]", path, index), errors);
+                }
+            }
+        }
+        Value::String(text) => {
+            let length = text.chars().count() as u64;
+            if let Some(min) = schema.get("minLength").and_then(Value::as_u64)
+                && length < min
+            {
+                errors.push(format!("{}: expected at least {} characters", path, min));
+            }
+            if let Some(max) = schema.get("maxLength").and_then(Value::as_u64)
+                && length > max
+            {
+                errors.push(format!("{}: expected at most {} characters", path, max));
+            }
+        }
+        Value::Number(number) => {
+            let number = number.as_f64().unwrap_or_default();
+            if let Some(min) = schema.get("minimum").and_then(Value::as_f64)
+                && number < min
+            {
+                errors.push(format!("{}: must be at least {}", path, min));
+            }
+            if let Some(max) = schema.get("maximum").and_then(Value::as_f64)
+                && number > max
+            {
+                errors.push(format!("{}: must be at most {}", path, max));
+            }
+        }
+        _ => {}
+    }
+
+    if let Some(Value::Array(schemas)) = schema.get("allOf") {
+        for sub_schema in schemas {
+            validate_at(sub_schema, value, path, errors);
+        }
+    }
+
+    if let Some(Value::Array(schemas)) = schema.get("anyOf")
+        && !schemas.iter().any(|s| validate(s, value).is_empty())
+    {
+        errors.push(format!("{}: value does not match any allowed schema", path));
+    }
+
+    if let Some(Value::Array(schemas)) = schema.get("oneOf") {
+        let matches = schemas
+            .iter()
+            .filter(|s| validate(s, value).is_empty())
+            .count();
+        if matches != 1 {
+            errors.push(format!(
+                "{}: value must match exactly one schema, matched {}",
+                path, matches
+            ));
+        }
+    }
+}
+
+fn matches_type(expected: &Value, value: &Value) -> bool {
+    match expected {
+        Value::String(name) => matches_type_name(name, value),
+        Value::Array(names) => names
+            .iter()
+            .filter_map(Value::as_str)
+            .any(|name| matches_type_name(name, value)),
+        _ => true,
+    }
+}
+
+fn matches_type_name(name: &str, value: &Value) -> bool {
+    match name {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        // JSON Schema counts any number with a zero fractional part, like 1.0
+        "integer" => value.as_f64().is_some_and(|number| number.fract() == 0.0),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn reports_missing_required_properties() {
+        let schema = json!({ "type": "object", "required": ["summary", "files"] });
+        let errors = validate(&schema, &json!({ "summary": "done" }));
+        assert_eq!(errors, vec!["$: missing required property 'files'"]);
+    }
+
+    #[test]
+    fn rejects_additional_properties_when_disallowed() {
+        let schema = json!({
+            "type": "object",
+            "properties": { "summary": { "type": "string" } },
+            "additionalProperties": false
+        });
+        assert!(validate(&schema, &json!({ "summary": "done" })).is_empty());
+        let errors = validate(&schema, &json!({ "summary": "done", "extra": 1 }));
+        assert_eq!(errors, vec!["$.extra: property is not allowed"]);
+    }
+
+    #[test]
+    fn validates_additional_properties_against_a_schema() {
+        let schema = json!({ "type": "object", "additionalProperties": { "type": "integer" } });
+        assert!(validate(&schema, &json!({ "a": 1, "b": 2 })).is_empty());
+        let errors = validate(&schema, &json!({ "a": "one" }));
+        assert_eq!(errors, vec!["$.a: expected type \"integer\", found string"]);
+    }
+
+    #[test]
+    fn validates_each_item() {
+        let schema = json!({ "type": "array", "items": { "type": "string" }, "maxItems": 2 });
+        assert!(validate(&schema, &json!(["a", "b"])).is_empty());
+        let errors = validate(&schema, &json!(["a", 2, "c"]));
+        assert_eq!(
+            errors,
+            vec![
+                "$: expected at most 2 items",
+                "$[1]: expected type \"string\", found number",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_nested_paths() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "files": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "required": ["path"],
+                        "properties": { "lines": { "type": "integer", "minimum": 0 } }
+                    }
+                }
+            }
+        });
+        let errors = validate(
+            &schema,
+            &json!({ "files": [{ "path": "a.rs" }, { "lines": -1 }] }),
+        );
+        assert_eq!(
+            errors,
+            vec![
+                "$.files[1]: missing required property 'path'",
+                "$.files[1].lines: must be at least 0",
+            ]
+        );
+    }
+
+    #[test]
+    fn any_of_needs_one_match() {
+        let schema = json!({ "anyOf": [{ "type": "string" }, { "type": "integer" }] });
+        assert!(validate(&schema, &json!("text")).is_empty());
+        assert!(validate(&schema, &json!(3)).is_empty());
+        assert_eq!(
+            validate(&schema, &json!(true)),
+            vec!["$: value does not match any allowed schema"]
+        );
+    }
+
+    #[test]
+    fn one_of_needs_exactly_one_match() {
+        let schema = json!({ "oneOf": [{ "type": "number" }, { "type": "integer" }] });
+        assert!(validate(&schema, &json!(1.5)).is_empty());
+        assert_eq!(
+            validate(&schema, &json!(2)),
+            vec!["$: value must match exactly one schema, matched 2"]
+        );
+        assert_eq!(
+            validate(&schema, &json!("2")),
+            vec!["$: value must match exactly one schema, matched 0"]
+        );
+    }
+
+    #[test]
+    fn integers_may_have_a_zero_fraction() {
+        let schema = json!({ "type": "integer" });
+        assert!(validate(&schema, &json!(1)).is_empty());
+        assert!(validate(&schema, &json!(1.0)).is_empty());
+        assert_eq!(
+            validate(&schema, &json!(1.5)),
+            vec!["$: expected type \"integer\", found number"]
+        );
+    }
+}
@@ -0,0 +1,85 @@
+use crate::bindings::theater::simple::environment::get_var;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// `{"$env": "NAME"}` is replaced with the environment variable when spawning
+const ENV_KEY: &str = "$env";
+/// `{"$secret": value}` is replaced with `value` when spawning and hidden
+/// from logs and responses. The value itself stays in our config and state.
+pub const SECRET_KEY: &str = "$secret";
+
+const MASKED: &str = "[redacted]";
+
+/// A stdio server argument or environment value, given inline or as a
+/// reference resolved when chat-state is spawned
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum ConfigString {
+    Plain(String),
+    Env {
+        #[serde(rename = "$env")]
+        env: String,
+    },
+    Secret {
+        #[serde(rename = "$secret")]
+        secret: String,
+    },
+}
+
+/// Replaces `$env` and `$secret` references with their values. Only called
+/// on config that is about to be handed to a spawned actor, so environment
+/// values never end up in our state.
+pub fn resolve(value: &Value) -> Result<Value, String> {
+    match value {
+        Value::Object(object) => {
+            if let Some(name) = marker(object, ENV_KEY) {
+                let name = name
+                    .as_str()
+                    .ok_or_else(|| format!("{} must be the name of a variable", ENV_KEY))?;
+                return get_var(name).map(Value::from).ok_or_else(|| {
+                    format!("Environment variable {} is not set or not accessible", name)
+                });
+            }
+            if let Some(secret) = marker(object, SECRET_KEY) {
+                return resolve(secret);
+            }
+            object
+                .iter()
+                .map(|(key, value)| Ok((key.clone(), resolve(value)?)))
+                .collect::<Result<Map<_, _>, String>>()
+                .map(Value::Object)
+        }
+        Value::Array(items) => items
+            .iter()
+            .map(resolve)
+            .collect::<Result<Vec<_>, _>>()
+            .map(Value::Array),
+        other => Ok(other.clone()),
+    }
+}
+
+/// Hides the values of `$secret` markers, for config we send back out
+pub fn mask(value: &Value) -> Value {
+    match value {
+        Value::Object(object) if marker(object, SECRET_KEY).is_some() => {
+            serde_json::json!({ SECRET_KEY: MASKED })
+        }
+        Value::Object(object) => Value::Object(
+            object
+                .iter()
+                .map(|(key, value)| (key.clone(), mask(value)))
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(mask).collect()),
+        other => other.clone(),
+    }
+}
+
+/// The value of a single-key object, which is what a reference looks like
+fn marker<'a>(object: &'a Map<String, Value>, key: &str) -> Option<&'a Value> {
+    if object.len() == 1 {
+        object.get(key)
+    } else {
+        None
+    }
+}
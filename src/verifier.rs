@@ -0,0 +1,348 @@
+use crate::bindings::theater::simple::message_server_host::{request, send};
+use crate::bindings::theater::simple::supervisor::{spawn, stop_child};
+use crate::logging::{info, warn};
+use crate::models::{self, ModelPresets};
+use crate::protocol::{ChatStateRequest, ChatStateResponse, McpActorRequest, McpResponse};
+use crate::secrets;
+use crate::{request_chat_state, spawn_chat_state_actor};
+use genai_types::{Message, MessageContent, messages::Role};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, from_slice, to_vec};
+
+const DEFAULT_REVIEW_PROMPT: &str = "You are a strict reviewer. You are given the transcript of an AI assistant working on a task and the result it reported. Decide whether the task was actually completed correctly and completely.";
+
+const VERDICT_INSTRUCTION: &str = "Respond with only a JSON object of the form {\"pass\": true|false, \"feedback\": \"...\"}. When the work is not acceptable, the feedback must tell the assistant exactly what to fix.";
+
+/// How a completed task is checked before the task manager accepts it
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct VerifierConfig {
+    #[serde(flatten)]
+    pub kind: VerifierKind,
+    /// How many times the verifier may send the task back before it fails
+    pub max_rejections: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum VerifierKind {
+    /// A second model reviews the transcript and result
+    Model {
+        /// A proxy config or preset name; defaults to the task's current model
+        model_proxy: Option<Value>,
+        review_prompt: Option<String>,
+    },
+    /// An MCP tool is called with the transcript and result
+    Tool {
+        /// An already-running MCP actor, used instead of spawning one
+        actor_id: Option<String>,
+        manifest_path: Option<String>,
+        init_state: Option<Value>,
+        tool: String,
+    },
+}
+
+/// The verifier's decision, reported in the completion payload
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Verdict {
+    pub passed: bool,
+    pub feedback: Option<String>,
+    /// Set when the verifier itself could not run; the result is then accepted
+    pub error: Option<String>,
+    pub rejections: u32,
+}
+
+#[derive(Deserialize, Debug)]
+struct VerdictReply {
+    #[serde(alias = "passed")]
+    pass: bool,
+    feedback: Option<String>,
+}
+
+/// Runs the verifier against the finished task. Failures to run the
+/// verifier produce a passing verdict with `error` set so a broken
+/// verifier doesn't hold the task hostage.
+pub fn verify(
+    config: &VerifierConfig,
+    transcript: &[Message],
+    result: Option<&Value>,
+    current_model_proxy: &Value,
+    presets: &ModelPresets,
+    rejections: u32,
+) -> Verdict {
+    let reply = match &config.kind {
+        VerifierKind::Model {
+            model_proxy,
+            review_prompt,
+        } => verify_with_model(
+            model_proxy.as_ref(),
+            review_prompt.as_deref(),
+            transcript,
+            result,
+            current_model_proxy,
+            presets,
+        ),
+        VerifierKind::Tool {
+            actor_id,
+            manifest_path,
+            init_state,
+            tool,
+        } => verify_with_tool(
+            actor_id.as_deref(),
+            manifest_path.as_deref(),
+            init_state.as_ref(),
+            tool,
+            transcript,
+            result,
+        ),
+    };
+
+    match reply {
+        Ok(reply) => Verdict {
+            passed: reply.pass,
+            feedback: reply.feedback,
+            error: None,
+            rejections,
+        },
+        Err(e) => {
+            warn(&format!("Verifier failed to run: {}", e));
+            Verdict {
+                passed: true,
+                feedback: None,
+                error: Some(e),
+                rejections,
+            }
+        }
+    }
+}
+
+fn verify_with_model(
+    model_proxy: Option<&Value>,
+    review_prompt: Option<&str>,
+    transcript: &[Message],
+    result: Option<&Value>,
+    current_model_proxy: &Value,
+    presets: &ModelPresets,
+) -> Result<VerdictReply, String> {
+    let model_proxy = match model_proxy {
+        Some(Value::String(name)) => models::get_preset(presets, name)?.model_proxy(),
+        Some(proxy) => proxy.clone(),
+        None => current_model_proxy.clone(),
+    };
+    info(&format!(
+        "Verifying task with model {}",
+        models::model_label(&model_proxy)
+    ));
+
+    let system_prompt = format!(
+        "{}\n\n{}",
+        review_prompt.unwrap_or(DEFAULT_REVIEW_PROMPT),
+        VERDICT_INSTRUCTION
+    );
+    let reply_text = ask_model(
+        &model_proxy,
+        "Task verification",
+        &system_prompt,
+        review_request(transcript, result),
+    )?;
+    parse_reply(&reply_text)
+}
+
+/// Asks a one-off question of a model on a throwaway chat-state and returns
+/// the text of its reply
+pub fn ask_model(
+    model_proxy: &Value,
+    title: &str,
+    system_prompt: &str,
+    question: String,
+) -> Result<String, String> {
+    let config = serde_json::json!({
+        "config": {
+            "model_proxy": model_proxy,
+            "temperature": 0.0,
+            "max_tokens": 4096,
+            "system_prompt": system_prompt,
+            "title": title,
+            "mcp_servers": [],
+        }
+    });
+    let chat_actor_id = spawn_chat_state_actor(&config)?;
+
+    let reply = ask(&chat_actor_id, question);
+
+    if let Err(e) = stop_child(&chat_actor_id) {
+        warn(&format!("Failed to stop {} chat state actor: {}", title, e));
+    }
+    reply
+}
+
+fn ask(chat_actor_id: &str, question: String) -> Result<String, String> {
+    let message = Message {
+        role: Role::User,
+        content: vec![MessageContent::Text { text: question }],
+    };
+    let request_data = to_vec(&ChatStateRequest::AddMessage { message })
+        .map_err(|e| format!("Failed to serialize question: {}", e))?;
+    send(chat_actor_id, &request_data).map_err(|e| format!("Failed to send question: {:?}", e))?;
+
+    if let ChatStateResponse::Error { error } =
+        request_chat_state(chat_actor_id, &ChatStateRequest::GenerateCompletion)?
+    {
+        return Err(format!("Model failed: {}", error.message));
+    }
+
+    let messages = match request_chat_state(chat_actor_id, &ChatStateRequest::GetHistory)? {
+        ChatStateResponse::History { messages } => messages,
+        other => return Err(format!("Unexpected chat state response: {:?}", other)),
+    };
+
+    messages
+        .iter()
+        .rev()
+        .find(|message| matches!(message.role, Role::Assistant))
+        .map(message_text)
+        .ok_or_else(|| "Model did not reply".to_string())
+}
+
+fn verify_with_tool(
+    actor_id: Option<&str>,
+    manifest_path: Option<&str>,
+    init_state: Option<&Value>,
+    tool: &str,
+    transcript: &[Message],
+    result: Option<&Value>,
+) -> Result<VerdictReply, String> {
+    // Use the running actor if given, otherwise spawn one just for this check
+    let (mcp_actor_id, spawned) = match (actor_id, manifest_path) {
+        (Some(actor_id), _) => (actor_id.to_string(), false),
+        (None, Some(manifest_path)) => {
+            let init_bytes = init_state
+                .map(secrets::resolve)
+                .transpose()?
+                .as_ref()
+                .map(to_vec)
+                .transpose()
+                .map_err(|e| format!("Failed to serialize verifier init state: {}", e))?;
+            let actor_id = spawn(manifest_path, init_bytes.as_deref())
+                .map_err(|e| format!("Failed to spawn verifier tool actor: {}", e))?;
+            (actor_id, true)
+        }
+        (None, None) => {
+            return Err("Tool verifier needs either actor_id or manifest_path".to_string());
+        }
+    };
+    info(&format!(
+        "Verifying task with tool {} on {}",
+        tool, mcp_actor_id
+    ));
+
+    let call = McpActorRequest::ToolsCall {
+        name: tool.to_string(),
+        args: serde_json::json!({
+            "transcript": transcript,
+            "result": result,
+        }),
+    };
+    let response = to_vec(&call)
+        .map_err(|e| format!("Failed to serialize tool call: {}", e))
+        .and_then(|request_data| {
+            request(&mcp_actor_id, &request_data)
+                .map_err(|e| format!("Verifier tool call failed: {}", e))
+        })
+        .and_then(|response_data| {
+            from_slice::<McpResponse>(&response_data)
+                .map_err(|e| format!("Failed to parse verifier tool response: {}", e))
+        });
+
+    if spawned && let Err(e) = stop_child(&mcp_actor_id) {
+        warn(&format!("Failed to stop verifier tool actor: {}", e));
+    }
+
+    let response = response?;
+    if let Some(error) = response.error {
+        return Err(format!(
+            "Verifier tool returned error {}: {}",
+            error.code, error.message
+        ));
+    }
+
+    let tool_result = response.result.unwrap_or(Value::Null);
+    let text = tool_result_text(&tool_result);
+
+    // A tool reporting an error is treated as a rejection with its output as feedback
+    if tool_result.get("isError").and_then(Value::as_bool) == Some(true) {
+        return Ok(VerdictReply {
+            pass: false,
+            feedback: Some(text),
+        });
+    }
+
+    parse_reply(&text)
+}
+
+fn review_request(transcript: &[Message], result: Option<&Value>) -> String {
+    let rendered: Vec<String> = transcript
+        .iter()
+        .map(|message| {
+            let role = match message.role {
+                Role::User => "user",
+                Role::Assistant => "assistant",
+                Role::System => "system",
+            };
+            format!("[{}]\n{}", role, message_text(message))
+        })
+        .collect();
+
+    format!(
+        "Transcript:\n\n{}\n\nReported result:\n{}",
+        rendered.join("\n\n"),
+        result
+            .map(Value::to_string)
+            .unwrap_or_else(|| "(none)".to_string())
+    )
+}
+
+fn message_text(message: &Message) -> String {
+    message
+        .content
+        .iter()
+        .map(|content| match content {
+            MessageContent::Text { text } => text.clone(),
+            MessageContent::ToolUse { name, input, .. } => {
+                format!("(called tool {} with {})", name, input)
+            }
+            MessageContent::ToolResult { tool_use_id, .. } => {
+                format!("(tool result for {})", tool_use_id)
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Extracts the text blocks of an MCP `tools/call` result
+fn tool_result_text(tool_result: &Value) -> String {
+    match tool_result.get("content").and_then(Value::as_array) {
+        Some(content) => content
+            .iter()
+            .filter_map(|block| block.get("text").and_then(Value::as_str))
+            .collect::<Vec<&str>>()
+            .join("\n"),
+        None => tool_result.to_string(),
+    }
+}
+
+/// Finds the verdict JSON object in a free-form reply
+fn parse_reply(text: &str) -> Result<VerdictReply, String> {
+    parse_json_reply(text, "verdict")
+}
+
+/// Finds the JSON object in a free-form model reply
+pub fn parse_json_reply<T: DeserializeOwned>(text: &str, what: &str) -> Result<T, String> {
+    let start = text.find('{');
+    let end = text.rfind('}');
+    match (start, end) {
+        (Some(start), Some(end)) if start < end => serde_json::from_str(&text[start..=end])
+            .map_err(|e| format!("Failed to parse {}: {}", what, e)),
+        _ => Err(format!("Reply contained no {}: {}", what, text)),
+    }
+}